@@ -0,0 +1,443 @@
+//! Encrypted bundles of a project's devsecrets directory.
+//!
+//! A bundle is a tar archive encrypted with [age](https://age-encryption.org). The first entry
+//! of the archive is a manifest recording the project's devsecrets ID, followed by the secret
+//! files under a `secrets/` prefix.
+
+use age::secrecy::SecretString;
+use devsecrets_core::{DevSecretsDir, DevSecretsId, DevSecretsRootDir};
+use serde::{Deserialize, Serialize};
+use std::io::{BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+
+const MANIFEST_NAME: &str = "devsecrets-bundle.json";
+const SECRETS_PREFIX: &str = "secrets";
+const BUNDLE_VERSION: u32 = 1;
+
+/// The environment variable that can be used to provide a passphrase without prompting.
+pub const PASSPHRASE_ENV_VAR: &str = "DEVSECRETS_PASSPHRASE";
+
+#[derive(Serialize, Deserialize)]
+struct BundleManifest {
+    version: u32,
+    id: String,
+}
+
+/// How a bundle is encrypted.
+pub enum BundleKey {
+    Passphrase(SecretString),
+    Recipients(Vec<age::x25519::Recipient>),
+}
+
+/// Reads a passphrase from `PASSPHRASE_ENV_VAR`, or prompts for it on the terminal.
+pub fn read_passphrase(confirm: bool) -> anyhow::Result<SecretString> {
+    if let Ok(passphrase) = std::env::var(PASSPHRASE_ENV_VAR) {
+        if passphrase.is_empty() {
+            anyhow::bail!("The passphrase in {} must not be empty", PASSPHRASE_ENV_VAR);
+        }
+        return Ok(SecretString::from(passphrase));
+    }
+
+    let passphrase = rpassword::prompt_password("Bundle passphrase: ")?;
+    if passphrase.is_empty() {
+        anyhow::bail!("The passphrase must not be empty");
+    }
+    if confirm && rpassword::prompt_password("Confirm passphrase: ")? != passphrase {
+        anyhow::bail!("Passphrases did not match");
+    }
+    Ok(SecretString::from(passphrase))
+}
+
+pub fn parse_recipient(recipient: &str) -> anyhow::Result<age::x25519::Recipient> {
+    recipient
+        .parse()
+        .map_err(|e| anyhow::anyhow!("Invalid age recipient {:?}: {}", recipient, e))
+}
+
+fn append_file(
+    builder: &mut tar::Builder<impl std::io::Write>,
+    path: impl AsRef<Path>,
+    contents: &[u8],
+) -> std::io::Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(contents.len() as u64);
+    header.set_mode(0o600);
+    header.set_cksum();
+    builder.append_data(&mut header, path, contents)
+}
+
+fn write_bundle(
+    dir: &DevSecretsDir,
+    id: &DevSecretsId,
+    output: &Path,
+    encryptor: age::Encryptor,
+) -> anyhow::Result<usize> {
+    let file = std::fs::File::create(output)
+        .map_err(|e| anyhow::anyhow!("Could not create {:?}: {}", output, e))?;
+    let mut builder = tar::Builder::new(encryptor.wrap_output(file)?);
+
+    let manifest = serde_json::to_vec_pretty(&BundleManifest {
+        version: BUNDLE_VERSION,
+        id: id.id_str().to_string(),
+    })?;
+    append_file(&mut builder, MANIFEST_NAME, &manifest)?;
+
+    let files = dir.files()?;
+    for relpath in &files {
+        let contents = std::fs::read(dir.path().join(relpath))?;
+        append_file(
+            &mut builder,
+            Path::new(SECRETS_PREFIX).join(relpath),
+            &contents,
+        )?;
+    }
+
+    builder.into_inner()?.finish()?.sync_all()?;
+    Ok(files.len())
+}
+
+/// Writes the contents of `dir` to an encrypted bundle at `output`, returning the number of
+/// files written.
+///
+/// The bundle is written to a temporary file next to `output` and renamed once complete, so
+/// that a failed export never leaves a truncated bundle behind.
+pub fn export_bundle(
+    dir: &DevSecretsDir,
+    id: &DevSecretsId,
+    output: &Path,
+    key: BundleKey,
+) -> anyhow::Result<usize> {
+    let encryptor = match key {
+        BundleKey::Passphrase(passphrase) => age::Encryptor::with_user_passphrase(passphrase),
+        BundleKey::Recipients(recipients) => {
+            age::Encryptor::with_recipients(recipients.iter().map(|r| r as &dyn age::Recipient))?
+        }
+    };
+
+    let file_name = output
+        .file_name()
+        .ok_or_else(|| anyhow::anyhow!("{:?} is not a valid bundle path", output))?;
+    let partial = output.with_file_name(format!(".{}.partial", file_name.to_string_lossy()));
+    let result = write_bundle(dir, id, &partial, encryptor).and_then(|count| {
+        std::fs::rename(&partial, output)
+            .map_err(|e| anyhow::anyhow!("Could not create {:?}: {}", output, e))?;
+        Ok(count)
+    });
+    if result.is_err() {
+        let _ = std::fs::remove_file(&partial);
+    }
+    result
+}
+
+/// The ways a bundle can be decrypted.
+pub struct BundleIdentities {
+    /// Paths to age identity files.
+    pub identity_files: Vec<PathBuf>,
+    /// Called to obtain the passphrase if the bundle is passphrase-encrypted.
+    pub passphrase: fn() -> anyhow::Result<SecretString>,
+}
+
+fn decrypt_bundle(bundle: &Path, identities: &BundleIdentities) -> anyhow::Result<Box<dyn Read>> {
+    let file = std::fs::File::open(bundle)
+        .map_err(|e| anyhow::anyhow!("Could not open {:?}: {}", bundle, e))?;
+    let decryptor = age::Decryptor::new(BufReader::new(file))?;
+
+    let reader = if decryptor.is_scrypt() {
+        let identity = age::scrypt::Identity::new((identities.passphrase)()?);
+        decryptor.decrypt(std::iter::once(&identity as &dyn age::Identity))?
+    } else {
+        if identities.identity_files.is_empty() {
+            anyhow::bail!("The bundle is encrypted to age recipients; pass --identity");
+        }
+        let mut keys = Vec::new();
+        for path in &identities.identity_files {
+            let identity_file = age::IdentityFile::from_file(path.to_string_lossy().into())
+                .map_err(|e| anyhow::anyhow!("Could not read identity file {:?}: {}", path, e))?;
+            keys.extend(identity_file.into_identities()?);
+        }
+        decryptor.decrypt(keys.iter().map(|key| key.as_ref()))?
+    };
+    Ok(Box::new(reader))
+}
+
+/// The result of a successful `import_bundle()`.
+pub struct ImportedBundle {
+    pub id: DevSecretsId,
+    pub dir: DevSecretsDir,
+    pub files: Vec<PathBuf>,
+}
+
+/// Unpacks an encrypted bundle into the devsecrets directory for the ID it records.
+///
+/// If `expected_id` is given, the bundle must have been exported for that ID. Existing files
+/// are only overwritten if `force` is true.
+pub fn import_bundle(
+    root: &DevSecretsRootDir,
+    bundle: &Path,
+    identities: &BundleIdentities,
+    expected_id: Option<&DevSecretsId>,
+    force: bool,
+) -> anyhow::Result<ImportedBundle> {
+    let mut archive = tar::Archive::new(decrypt_bundle(bundle, identities)?);
+    let mut entries = archive.entries()?;
+
+    let manifest: BundleManifest = match entries.next() {
+        Some(entry) => {
+            let entry = entry?;
+            if entry.path()? != Path::new(MANIFEST_NAME) {
+                anyhow::bail!("The bundle does not start with a manifest");
+            }
+            serde_json::from_reader(entry)?
+        }
+        None => anyhow::bail!("The bundle is empty"),
+    };
+    if manifest.version != BUNDLE_VERSION {
+        anyhow::bail!("Unsupported bundle version {}", manifest.version);
+    }
+    let id = DevSecretsId::parse(&manifest.id)
+        .ok_or_else(|| anyhow::anyhow!("The bundle has an invalid ID {:?}", manifest.id))?;
+    if let Some(expected_id) = expected_id {
+        if expected_id.id_str() != id.id_str() {
+            anyhow::bail!(
+                "The bundle was exported for devsecrets ID {}, but this project has ID {}",
+                id.id_str(),
+                expected_id.id_str()
+            );
+        }
+    }
+
+    // Read all files before writing any, so that a corrupt bundle does not leave a partially
+    // imported directory behind.
+    let mut files = Vec::new();
+    for entry in entries {
+        let mut entry = entry?;
+        if entry.header().entry_type().is_dir() {
+            continue;
+        }
+        if !entry.header().entry_type().is_file() {
+            anyhow::bail!(
+                "The bundle contains an unsupported entry {:?}",
+                entry.path()?
+            );
+        }
+        let path = entry.path()?.into_owned();
+        let relpath = match path.strip_prefix(SECRETS_PREFIX) {
+            Ok(relpath) if !relpath.as_os_str().is_empty() => relpath.to_path_buf(),
+            _ => anyhow::bail!("Unexpected bundle entry {:?}", path),
+        };
+        devsecrets_core::check_relative_path(&relpath).map_err(anyhow::Error::msg)?;
+        let mut contents = Vec::new();
+        entry.read_to_end(&mut contents)?;
+        files.push((relpath, contents));
+    }
+
+    let dir = root.ensure_child(&id)?;
+    if !force {
+        let existing: Vec<_> = files
+            .iter()
+            .filter(|(relpath, _)| dir.path().join(relpath).exists())
+            .map(|(relpath, _)| relpath.display().to_string())
+            .collect();
+        if !existing.is_empty() {
+            anyhow::bail!(
+                "These files already exist in the devsecrets directory (use --force to \
+                 overwrite): {}",
+                existing.join(", ")
+            );
+        }
+    }
+
+    for (relpath, contents) in &files {
+        let target = dir.path().join(relpath);
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        devsecrets_core::create_private_file(&target)?.write_all(contents)?;
+    }
+
+    Ok(ImportedBundle {
+        id,
+        dir,
+        files: files.into_iter().map(|(relpath, _)| relpath).collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use age::secrecy::ExposeSecret;
+
+    /// A temporary directory that is removed when dropped.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new() -> Self {
+            let path = std::env::temp_dir().join(format!(
+                "devsecrets-bundle-test-{}",
+                DevSecretsId::new_unique().id_str()
+            ));
+            std::fs::create_dir_all(&path).unwrap();
+            TempDir(path)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn no_passphrase() -> anyhow::Result<SecretString> {
+        panic!("Bundles in these tests are not passphrase-encrypted");
+    }
+
+    /// Generates an age identity, returning its recipient and the identities to decrypt with.
+    fn generate_identity(tmp: &TempDir) -> (age::x25519::Recipient, BundleIdentities) {
+        let identity = age::x25519::Identity::generate();
+        let identity_path = tmp.0.join("identity.txt");
+        std::fs::write(&identity_path, identity.to_string().expose_secret()).unwrap();
+        (
+            identity.to_public(),
+            BundleIdentities {
+                identity_files: vec![identity_path],
+                passphrase: no_passphrase,
+            },
+        )
+    }
+
+    /// Writes a bundle whose entry names are used as given, bypassing the checks `tar` makes
+    /// when building archives.
+    fn write_raw_bundle(
+        path: &Path,
+        recipient: &age::x25519::Recipient,
+        id: &DevSecretsId,
+        name: &[u8],
+    ) {
+        let encryptor =
+            age::Encryptor::with_recipients(std::iter::once(recipient as &dyn age::Recipient))
+                .unwrap();
+        let file = std::fs::File::create(path).unwrap();
+        let mut builder = tar::Builder::new(encryptor.wrap_output(file).unwrap());
+        let manifest = serde_json::to_vec(&BundleManifest {
+            version: BUNDLE_VERSION,
+            id: id.id_str().to_string(),
+        })
+        .unwrap();
+        append_file(&mut builder, MANIFEST_NAME, &manifest).unwrap();
+
+        let contents = b"escaped";
+        let mut header = tar::Header::new_gnu();
+        header.as_old_mut().name[..name.len()].copy_from_slice(name);
+        header.set_entry_type(tar::EntryType::Regular);
+        header.set_size(contents.len() as u64);
+        header.set_mode(0o600);
+        header.set_cksum();
+        builder.append(&header, &contents[..]).unwrap();
+        builder.into_inner().unwrap().finish().unwrap();
+    }
+
+    #[test]
+    fn export_and_import_round_trip() {
+        let tmp = TempDir::new();
+        let source = tmp.0.join("source");
+        std::fs::create_dir_all(source.join("nested")).unwrap();
+        std::fs::write(source.join("api_key.txt"), "hunter2").unwrap();
+        std::fs::write(source.join("nested/db.toml"), "url = \"pg://\"").unwrap();
+        let id = DevSecretsId::new_unique();
+        let (recipient, identities) = generate_identity(&tmp);
+
+        let bundle = tmp.0.join("secrets.age");
+        let count = export_bundle(
+            &DevSecretsDir::from_path(&source),
+            &id,
+            &bundle,
+            BundleKey::Recipients(vec![recipient]),
+        )
+        .unwrap();
+        assert_eq!(count, 2);
+        assert!(!tmp.0.join(".secrets.age.partial").exists());
+
+        let root = DevSecretsRootDir::ensure_with_config_root(&tmp.0).unwrap();
+        let imported = import_bundle(&root, &bundle, &identities, Some(&id), false).unwrap();
+        assert_eq!(imported.id.id_str(), id.id_str());
+        assert_eq!(
+            imported.files,
+            vec![
+                PathBuf::from("api_key.txt"),
+                PathBuf::from("nested/db.toml")
+            ]
+        );
+        let target = imported.dir.path();
+        assert_eq!(
+            std::fs::read_to_string(target.join("api_key.txt")).unwrap(),
+            "hunter2"
+        );
+        assert_eq!(
+            std::fs::read_to_string(target.join("nested/db.toml")).unwrap(),
+            "url = \"pg://\""
+        );
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = target
+                .join("api_key.txt")
+                .metadata()
+                .unwrap()
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        // Importing again only succeeds when overwriting is allowed.
+        assert!(import_bundle(&root, &bundle, &identities, Some(&id), false).is_err());
+        assert!(import_bundle(&root, &bundle, &identities, Some(&id), true).is_ok());
+        let other_id = DevSecretsId::new_unique();
+        assert!(import_bundle(&root, &bundle, &identities, Some(&other_id), true).is_err());
+    }
+
+    #[test]
+    fn failed_export_leaves_no_bundle() {
+        let tmp = TempDir::new();
+        let (recipient, _) = generate_identity(&tmp);
+        let bundle = tmp.0.join("secrets.age");
+        let result = export_bundle(
+            &DevSecretsDir::from_path(tmp.0.join("missing")),
+            &DevSecretsId::new_unique(),
+            &bundle,
+            BundleKey::Recipients(vec![recipient]),
+        );
+        assert!(result.is_err());
+        assert!(!bundle.exists());
+        assert!(!tmp.0.join(".secrets.age.partial").exists());
+    }
+
+    #[test]
+    fn import_rejects_entries_outside_the_secrets_dir() {
+        let names: &[&[u8]] = &[
+            b"secrets/../escaped.txt",
+            b"secrets/../../escaped.txt",
+            b"secrets/a/../../../escaped.txt",
+            b"/escaped.txt",
+            b"/secrets/escaped.txt",
+            b"escaped.txt",
+        ];
+        for name in names {
+            let tmp = TempDir::new();
+            let id = DevSecretsId::new_unique();
+            let (recipient, identities) = generate_identity(&tmp);
+            let bundle = tmp.0.join("secrets.age");
+            write_raw_bundle(&bundle, &recipient, &id, name);
+
+            let root = DevSecretsRootDir::ensure_with_config_root(&tmp.0).unwrap();
+            let result = import_bundle(&root, &bundle, &identities, None, false);
+            assert!(
+                result.is_err(),
+                "Entry {:?} was imported",
+                String::from_utf8_lossy(name)
+            );
+            assert!(!tmp.0.join("escaped.txt").exists());
+            assert!(!root.path().join("escaped.txt").exists());
+            assert!(root.get_child(&id).unwrap().is_none());
+        }
+    }
+}
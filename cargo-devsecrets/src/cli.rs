@@ -1,5 +1,53 @@
+use crate::env::Shell;
 use clap::{App, AppSettings, Arg, SubCommand};
 
+/// Arguments describing how secrets map to environment variables.
+fn env_mapping_args() -> [Arg<'static, 'static>; 3] {
+    [
+        Arg::with_name("env")
+            .long("env")
+            .short("e")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .value_name("NAME=PATH")
+            .help(
+                "Sets the variable NAME to the contents of the secret file PATH. May be given \
+                multiple times. Variables can also be configured in the \
+                [package.metadata.devsecrets.env] table of Cargo.toml.",
+            ),
+        Arg::with_name("dotenv")
+            .long("dotenv")
+            .takes_value(true)
+            .value_name("PATH")
+            .help(
+                "A dotenv file in the devsecrets directory to read variables from. Defaults to \
+                .env, if it exists.",
+            ),
+        Arg::with_name("no-dotenv")
+            .long("no-dotenv")
+            .conflicts_with("dotenv")
+            .help("Do not read variables from a dotenv file."),
+    ]
+}
+
+/// Arguments for commands that can act on every package in the workspace.
+fn package_selection_args() -> [Arg<'static, 'static>; 2] {
+    [
+        Arg::with_name("workspace")
+            .long("workspace")
+            .help("Act on every member of the workspace, instead of a single package."),
+        Arg::with_name("exclude")
+            .long("exclude")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .value_name("PACKAGENAME")
+            .requires("workspace")
+            .help("Exclude the package from --workspace. May be given multiple times."),
+    ]
+}
+
 pub fn build_cli() -> App<'static, 'static> {
     App::new("cargo")
         .bin_name("cargo")
@@ -29,11 +77,231 @@ pub fn build_cli() -> App<'static, 'static> {
                 )
                 .subcommand(
                     SubCommand::with_name("init")
-                        .about("Initializes a devsecret directory for the current crate"),
+                        .about("Initializes a devsecret directory for the current crate")
+                        .args(&package_selection_args())
+                        .arg(
+                            Arg::with_name("workspace-shared")
+                                .long("workspace-shared")
+                                .conflicts_with_all(&["workspace", "exclude"])
+                                .help(
+                                    "Write the ID file to the workspace root, so that it is \
+                                shared by all members without their own ID file.",
+                                ),
+                        )
+                        .arg(
+                            Arg::with_name("in-manifest")
+                                .long("in-manifest")
+                                .conflicts_with("workspace-shared")
+                                .help(
+                                    "Store the ID in the [package.metadata.devsecrets] table of \
+                                Cargo.toml, instead of an ID file.",
+                                ),
+                        ),
                 )
                 .subcommand(
                     SubCommand::with_name("path")
-                        .about("Prints the devsecret config path to stdout"),
+                        .about("Prints the devsecret config path to stdout")
+                        .args(&package_selection_args()),
+                )
+                .subcommand(
+                    SubCommand::with_name("ls")
+                        .about("Lists the files in the devsecret directory")
+                        .args(&package_selection_args())
+                        .arg(
+                            Arg::with_name("json")
+                                .long("json")
+                                .help("Print the listing as JSON."),
+                        )
+                        .arg(
+                            Arg::with_name("tree")
+                                .long("tree")
+                                .conflicts_with("json")
+                                .help("Print the listing as a tree."),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("run")
+                        .about("Runs a command with secrets provided as environment variables")
+                        .args(&env_mapping_args())
+                        .arg(
+                            Arg::with_name("COMMAND")
+                                .required(true)
+                                .multiple(true)
+                                .last(true)
+                                .help("The command to run, and its arguments"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("env")
+                        .about("Prints shell statements that export secrets as variables")
+                        .args(&env_mapping_args())
+                        .arg(
+                            Arg::with_name("shell")
+                                .long("shell")
+                                .takes_value(true)
+                                .possible_values(Shell::NAMES)
+                                .help(
+                                    "The shell to print statements for. Defaults to the shell \
+                                in $SHELL.",
+                                ),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("import")
+                        .about("Copies a file or directory into the devsecret directory")
+                        .arg(
+                            Arg::with_name("SOURCE")
+                                .required(true)
+                                .help("The file or directory to import"),
+                        )
+                        .arg(
+                            Arg::with_name("to")
+                                .long("to")
+                                .takes_value(true)
+                                .value_name("DIR")
+                                .help(
+                                    "The directory within the devsecrets directory to import \
+                                into. Defaults to the top level.",
+                                ),
+                        )
+                        .arg(Arg::with_name("split-dotenv").long("split-dotenv").help(
+                            "Treat SOURCE as a dotenv file, and write each variable to \
+                                its own file named after the variable.",
+                        ))
+                        .arg(
+                            Arg::with_name("force")
+                                .long("force")
+                                .short("f")
+                                .help("Overwrite files that already exist."),
+                        )
+                        .arg(
+                            Arg::with_name("remove-originals")
+                                .long("remove-originals")
+                                .help(
+                                    "Overwrite and delete SOURCE after importing it. This is \
+                                best effort, and may not prevent recovery on all filesystems.",
+                                ),
+                        ),
+                )
+                .subcommand(SubCommand::with_name("install-hook").about(
+                    "Installs a git pre-commit hook that blocks commits containing \
+                        secret values",
+                ))
+                .subcommand(
+                    SubCommand::with_name("export")
+                        .about("Writes the devsecret directory to an encrypted bundle")
+                        .arg(
+                            Arg::with_name("OUTPUT")
+                                .required(true)
+                                .help("The bundle file to write"),
+                        )
+                        .arg(
+                            Arg::with_name("recipient")
+                                .long("recipient")
+                                .short("r")
+                                .takes_value(true)
+                                .multiple(true)
+                                .number_of_values(1)
+                                .value_name("RECIPIENT")
+                                .help(
+                                    "Encrypt to the given age public key instead of a \
+                                passphrase. May be given multiple times.",
+                                ),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("import-bundle")
+                        .about("Unpacks a bundle created by export into the devsecret directory")
+                        .arg(
+                            Arg::with_name("BUNDLE")
+                                .required(true)
+                                .help("The bundle file to read"),
+                        )
+                        .arg(
+                            Arg::with_name("identity")
+                                .long("identity")
+                                .short("i")
+                                .takes_value(true)
+                                .multiple(true)
+                                .number_of_values(1)
+                                .value_name("FILE")
+                                .help(
+                                    "An age identity file to decrypt the bundle with, if it \
+                                was encrypted to a recipient. May be given multiple times.",
+                                ),
+                        )
+                        .arg(
+                            Arg::with_name("force")
+                                .long("force")
+                                .short("f")
+                                .help("Overwrite files that already exist."),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("projects")
+                        .about("Lists every devsecret directory and the projects that use it")
+                        .arg(
+                            Arg::with_name("json")
+                                .long("json")
+                                .help("Print the listing as JSON."),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("gc")
+                        .about(
+                            "Moves devsecret directories that are empty or whose projects no \
+                        longer exist to a trash directory",
+                        )
+                        .arg(
+                            Arg::with_name("dry-run")
+                                .long("dry-run")
+                                .short("n")
+                                .help("Only print the directories that would be collected."),
+                        )
+                        .arg(
+                            Arg::with_name("older-than")
+                                .long("older-than")
+                                .takes_value(true)
+                                .value_name("AGE")
+                                .help(
+                                    "Only collect directories that have not been modified in \
+                                AGE (e.g. \"30days\" or \"2weeks\").",
+                                ),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("doctor")
+                        .about("Checks the devsecrets setup of the current crate for problems")
+                        .args(&package_selection_args()),
+                )
+                .subcommand(
+                    SubCommand::with_name("scan")
+                        .about("Searches the repository for values from the devsecret directory")
+                        .arg(
+                            Arg::with_name("history")
+                                .long("history")
+                                .help("Also search every file in the git history."),
+                        )
+                        .arg(
+                            Arg::with_name("staged")
+                                .long("staged")
+                                .conflicts_with("history")
+                                .help(
+                                    "Only search the changes staged for commit. This is used \
+                                by the hook installed by install-hook.",
+                                ),
+                        )
+                        .arg(
+                            Arg::with_name("min-length")
+                                .long("min-length")
+                                .takes_value(true)
+                                .value_name("BYTES")
+                                .default_value("12")
+                                .help(
+                                    "Ignore secret values shorter than this, as they are \
+                                likely to match by coincidence.",
+                                ),
+                        ),
                 )
                 .subcommand(
                     SubCommand::with_name("completions")
@@ -41,7 +309,7 @@ pub fn build_cli() -> App<'static, 'static> {
                         .arg(
                             Arg::with_name("SHELL")
                                 .required(true)
-                                .possible_values(&["zsh", "bash", "fish"])
+                                .possible_values(Shell::NAMES)
                                 .help("The shell to generate completions for"),
                         ),
                 ),
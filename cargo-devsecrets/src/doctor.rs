@@ -0,0 +1,394 @@
+//! Diagnoses common problems with a project's devsecrets setup.
+
+use crate::error::CliError;
+use crate::workspace::{CargoWorkspace, PackageSelection};
+use cargo_metadata::Package;
+use devsecrets_core::{DevSecretsDir, DevSecretsId, IdSource, LocatedId, DEVSECRETS_ID_FILE};
+use std::path::Path;
+use std::process::Command;
+
+enum Status {
+    Ok,
+    Warn,
+    Fail,
+}
+
+struct Check {
+    status: Status,
+    message: String,
+    fix: Option<String>,
+}
+
+impl Check {
+    fn ok(message: impl Into<String>) -> Self {
+        Check {
+            status: Status::Ok,
+            message: message.into(),
+            fix: None,
+        }
+    }
+
+    fn warn(message: impl Into<String>, fix: impl Into<String>) -> Self {
+        Check {
+            status: Status::Warn,
+            message: message.into(),
+            fix: Some(fix.into()),
+        }
+    }
+
+    fn fail(message: impl Into<String>, fix: impl Into<String>) -> Self {
+        Check {
+            status: Status::Fail,
+            message: message.into(),
+            fix: Some(fix.into()),
+        }
+    }
+
+    fn print(&self) {
+        let label = match self.status {
+            Status::Ok => "ok",
+            Status::Warn => "warn",
+            Status::Fail => "FAIL",
+        };
+        println!("[{}] {}", label, self.message);
+        if let Some(fix) = &self.fix {
+            println!("       fix: {}", fix);
+        }
+    }
+}
+
+fn check_packages(
+    manifest_path: Option<&Path>,
+    selection: &PackageSelection,
+    checks: &mut Vec<Check>,
+) -> Vec<Package> {
+    let workspace = match CargoWorkspace::with_opt_manifest_path(manifest_path) {
+        Ok(workspace) => workspace,
+        Err(CliError::NotRunByCargo) => {
+            checks.push(Check::fail(
+                "The CARGO environment variable is not set",
+                "Run this tool as `cargo devsecrets` rather than `cargo-devsecrets`.",
+            ));
+            return Vec::new();
+        }
+        Err(e) => {
+            checks.push(Check::fail(
+                e.to_string(),
+                "Run this from within a crate, or pass --manifest-path.",
+            ));
+            return Vec::new();
+        }
+    };
+
+    match workspace.select_packages(selection) {
+        Ok(packages) => {
+            if let PackageSelection::Workspace { .. } = selection {
+                checks.push(Check::ok(format!(
+                    "Found {} packages in the workspace",
+                    packages.len()
+                )));
+            } else {
+                for package in &packages {
+                    checks.push(Check::ok(format!(
+                        "Found package {} at {}",
+                        package.name,
+                        package.manifest_path.display()
+                    )));
+                }
+            }
+            packages.into_iter().cloned().collect()
+        }
+        Err(e) => {
+            checks.push(Check::fail(
+                e.to_string(),
+                "Pass the package name with -p, or run this from within a package directory.",
+            ));
+            Vec::new()
+        }
+    }
+}
+
+/// Checks for an ID from the package manifest or shared by the workspace, for packages without
+/// their own ID file.
+fn check_other_id_sources(manifest_dir: &Path, checks: &mut Vec<Check>) -> Option<LocatedId> {
+    match devsecrets_core::locate_devsecrets_id(manifest_dir) {
+        Ok(Some(located)) => {
+            checks.push(Check::ok(format!(
+                "Using ID {} {}, from {}",
+                located.id.id_str(),
+                if located.source.is_shared() {
+                    "shared by the workspace"
+                } else {
+                    "in [package.metadata.devsecrets]"
+                },
+                located.source.path().display()
+            )));
+            Some(located)
+        }
+        Ok(None) => {
+            checks.push(Check::fail(
+                format!(
+                    "{} does not exist",
+                    manifest_dir.join(DEVSECRETS_ID_FILE).display()
+                ),
+                "Run `cargo devsecrets init`, or `cargo devsecrets init --workspace-shared` to \
+                 share one ID between the members of the workspace.",
+            ));
+            None
+        }
+        Err(e) => {
+            checks.push(Check::fail(
+                format!("Could not read the devsecrets ID: {}", e),
+                "Fix the [package.metadata.devsecrets] table in Cargo.toml, or the ID file or \
+                 [workspace.metadata.devsecrets] table in the workspace root.",
+            ));
+            None
+        }
+    }
+}
+
+fn check_id_file(manifest_dir: &Path, checks: &mut Vec<Check>) -> Option<LocatedId> {
+    let id_path = manifest_dir.join(DEVSECRETS_ID_FILE);
+    let contents = match std::fs::read_to_string(&id_path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return check_other_id_sources(manifest_dir, checks);
+        }
+        Err(e) => {
+            checks.push(Check::fail(
+                format!("Could not read {}: {}", id_path.display(), e),
+                "Check the file's permissions.",
+            ));
+            return None;
+        }
+    };
+
+    // Surrounding whitespace is ignored when reading the ID.
+    let id = match DevSecretsId::parse(contents.trim()) {
+        Some(id) => {
+            checks.push(Check::ok(format!(
+                "{} contains ID {}",
+                DEVSECRETS_ID_FILE,
+                id.id_str()
+            )));
+            Some(id)
+        }
+        None => {
+            checks.push(Check::fail(
+                format!("{} does not contain a valid UUID", id_path.display()),
+                "Restore the file from version control, or delete it and run \
+                 `cargo devsecrets init` to create a new ID.",
+            ));
+            None
+        }
+    };
+    id.map(|id| LocatedId {
+        id,
+        source: IdSource::PackageFile(id_path),
+    })
+}
+
+/// Returns the paths of the files tracked by git in `dir`, relative to it, or `None` if `dir`
+/// is not in a git repository.
+fn git_tracked_files(dir: &Path) -> Option<Vec<String>> {
+    let output = Command::new("git")
+        .args(["ls-files", "-z", "--full-name", "--", "."])
+        .current_dir(dir)
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let prefix_output = Command::new("git")
+        .args(["rev-parse", "--show-prefix"])
+        .current_dir(dir)
+        .output()
+        .ok()?;
+    let prefix = String::from_utf8_lossy(&prefix_output.stdout)
+        .trim()
+        .to_string();
+    Some(
+        String::from_utf8_lossy(&output.stdout)
+            .split('\0')
+            .filter_map(|path| path.strip_prefix(prefix.as_str()))
+            .filter(|path| !path.is_empty())
+            .map(str::to_string)
+            .collect(),
+    )
+}
+
+fn check_id_tracked(source: &IdSource, checks: &mut Vec<Check>) {
+    let path = source.path();
+    let dir = path.parent().expect("ID source must have a parent");
+    let file_name = path
+        .file_name()
+        .expect("ID source must be a file")
+        .to_string_lossy();
+    // Name the package's own ID file briefly; shared sources live elsewhere in the workspace.
+    let display_name = match source {
+        IdSource::PackageFile(_) => DEVSECRETS_ID_FILE.to_string(),
+        _ => path.display().to_string(),
+    };
+    match git_tracked_files(dir) {
+        None => checks.push(Check::warn(
+            "The package is not in a git repository",
+            format!(
+                "Make sure {} is committed to your version control system.",
+                display_name
+            ),
+        )),
+        Some(tracked) if tracked.iter().any(|path| *path == file_name) => {
+            checks.push(Check::ok(format!("{} is tracked by git", display_name)))
+        }
+        Some(_) => checks.push(Check::fail(
+            format!("{} is not tracked by git", display_name),
+            format!(
+                "Run `git add {}` and commit it, so that other checkouts use the same ID.",
+                display_name
+            ),
+        )),
+    }
+}
+
+fn check_secrets_dir(id: &DevSecretsId, checks: &mut Vec<Check>) -> Option<DevSecretsDir> {
+    let dir = devsecrets_core::DevSecretsRootDir::new().and_then(|root| match root {
+        Some(root) => root.get_child(id),
+        None => Ok(None),
+    });
+    let dir = match dir {
+        Ok(Some(dir)) => dir,
+        Ok(None) => {
+            checks.push(Check::fail(
+                "The devsecrets directory does not exist",
+                "Run `cargo devsecrets init`.",
+            ));
+            return None;
+        }
+        Err(e) => {
+            checks.push(Check::fail(
+                format!("Could not open the devsecrets directory: {}", e),
+                "Check that your config directory is readable.",
+            ));
+            return None;
+        }
+    };
+    checks.push(Check::ok(format!(
+        "The devsecrets directory is {}",
+        dir.path().display()
+    )));
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        match dir.path().metadata() {
+            Ok(metadata) if metadata.permissions().mode() & 0o077 != 0 => checks.push(Check::fail(
+                format!(
+                    "The devsecrets directory is accessible by other users (mode {:o})",
+                    metadata.permissions().mode() & 0o777
+                ),
+                format!("chmod 700 {}", dir.path().display()),
+            )),
+            Ok(_) => checks.push(Check::ok("The devsecrets directory is private")),
+            Err(e) => checks.push(Check::fail(
+                format!(
+                    "Could not read the devsecrets directory's permissions: {}",
+                    e
+                ),
+                "Check that your config directory is readable.",
+            )),
+        }
+    }
+
+    Some(dir)
+}
+
+fn check_secrets_not_in_repo(
+    dir: &DevSecretsDir,
+    manifest_dir: &Path,
+    tracked: Option<&[String]>,
+    checks: &mut Vec<Check>,
+) {
+    let files = match dir.files() {
+        Ok(files) => files,
+        Err(e) => {
+            checks.push(Check::fail(
+                format!("Could not list the devsecrets directory: {}", e),
+                "Check the directory's permissions.",
+            ));
+            return;
+        }
+    };
+
+    let mut found = false;
+    for relpath in files {
+        let relpath_str = relpath.to_string_lossy().replace('\\', "/");
+        let is_tracked = tracked.is_some_and(|tracked| tracked.contains(&relpath_str));
+        if is_tracked || manifest_dir.join(&relpath).exists() {
+            found = true;
+            checks.push(Check::fail(
+                format!(
+                    "Secret file {} is also present in the package{}",
+                    relpath_str,
+                    if is_tracked {
+                        " and tracked by git"
+                    } else {
+                        ""
+                    }
+                ),
+                format!(
+                    "Remove {} from the package and add it to .gitignore. If it was \
+                     committed, consider the secret leaked and rotate it.",
+                    manifest_dir.join(&relpath).display()
+                ),
+            ));
+        }
+    }
+    if !found {
+        checks.push(Check::ok("No secret files are present in the package"));
+    }
+}
+
+fn check_package(package: &Package, checks: &mut Vec<Check>) {
+    let manifest_dir = package
+        .manifest_path
+        .parent()
+        .expect("Manifest path must have a parent");
+    let tracked = git_tracked_files(manifest_dir);
+    let located = check_id_file(manifest_dir, checks);
+    if let Some(located) = &located {
+        check_id_tracked(&located.source, checks);
+    }
+    if let Some(dir) = located.and_then(|located| check_secrets_dir(&located.id, checks)) {
+        check_secrets_not_in_repo(&dir, manifest_dir, tracked.as_deref(), checks);
+    }
+}
+
+/// Prints the results of `checks`. Returns true if no check failed.
+fn print_checks(checks: &[Check]) -> bool {
+    for check in checks {
+        check.print();
+    }
+    !checks
+        .iter()
+        .any(|check| matches!(check.status, Status::Fail))
+}
+
+/// Runs all checks for the selected packages, printing the results. Returns true if no check
+/// failed.
+pub fn run_doctor(manifest_path: Option<&Path>, selection: &PackageSelection) -> bool {
+    let mut checks = Vec::new();
+    let packages = check_packages(manifest_path, selection, &mut checks);
+    let mut healthy = print_checks(&checks);
+
+    let show_names = matches!(selection, PackageSelection::Workspace { .. });
+    for package in &packages {
+        if show_names {
+            println!();
+            println!("{}:", package.name);
+        }
+        let mut checks = Vec::new();
+        check_package(package, &mut checks);
+        healthy &= print_checks(&checks);
+    }
+    healthy
+}
@@ -0,0 +1,216 @@
+//! A minimal parser for dotenv files.
+//!
+//! Supports `KEY=value` lines, blank lines, `#` comments, an optional leading `export`, and
+//! single- or double-quoted values. Double-quoted values may use the `\n`, `\r`, `\t`, `\"`,
+//! `\$` and `\\` escapes.
+
+/// Returns true if `name` can be used as an environment variable name.
+pub fn is_valid_var_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c == '_' || c.is_ascii_alphabetic() => (),
+        _ => return false,
+    }
+    chars.all(|c| c == '_' || c.is_ascii_alphanumeric())
+}
+
+fn parse_double_quoted(rest: &str) -> Result<(String, &str), String> {
+    let mut value = String::new();
+    let mut chars = rest.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Ok((value, &rest[i + 1..])),
+            '\\' => match chars.next() {
+                Some((_, 'n')) => value.push('\n'),
+                Some((_, 'r')) => value.push('\r'),
+                Some((_, 't')) => value.push('\t'),
+                Some((_, c @ '"')) | Some((_, c @ '\\')) | Some((_, c @ '$')) => value.push(c),
+                Some((_, c)) => {
+                    value.push('\\');
+                    value.push(c);
+                }
+                None => break,
+            },
+            c => value.push(c),
+        }
+    }
+    Err("unterminated double-quoted value".to_string())
+}
+
+fn parse_value(raw: &str) -> Result<String, String> {
+    let raw = raw.trim_start();
+    let (value, rest) = if let Some(rest) = raw.strip_prefix('\'') {
+        let end = rest
+            .find('\'')
+            .ok_or_else(|| "unterminated single-quoted value".to_string())?;
+        (rest[..end].to_string(), &rest[end + 1..])
+    } else if let Some(rest) = raw.strip_prefix('"') {
+        parse_double_quoted(rest)?
+    } else {
+        // Unquoted values end at a comment that starts the value or is preceded by whitespace.
+        let end = raw
+            .char_indices()
+            .find(|&(i, c)| c == '#' && (i == 0 || raw[..i].ends_with(char::is_whitespace)))
+            .map(|(i, _)| i)
+            .unwrap_or_else(|| raw.len());
+        return Ok(raw[..end].trim_end().to_string());
+    };
+
+    let rest = rest.trim_start();
+    if !rest.is_empty() && !rest.starts_with('#') {
+        return Err("unexpected characters after quoted value".to_string());
+    }
+    Ok(value)
+}
+
+/// Parses the contents of a dotenv file into its key/value pairs, in file order.
+pub fn parse(contents: &str) -> anyhow::Result<Vec<(String, String)>> {
+    let mut entries = Vec::new();
+    for (index, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = match line.strip_prefix("export") {
+            Some(rest) if rest.starts_with(char::is_whitespace) => rest.trim_start(),
+            _ => line,
+        };
+
+        let (key, raw_value) = match line.find('=') {
+            Some(eq) => (line[..eq].trim_end(), &line[eq + 1..]),
+            None => anyhow::bail!("line {}: expected KEY=VALUE", index + 1),
+        };
+        if !is_valid_var_name(key) {
+            anyhow::bail!("line {}: invalid variable name {:?}", index + 1, key);
+        }
+        let value =
+            parse_value(raw_value).map_err(|e| anyhow::anyhow!("line {}: {}", index + 1, e))?;
+        entries.push((key.to_string(), value));
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_pairs(contents: &str) -> Vec<(String, String)> {
+        parse(contents).unwrap()
+    }
+
+    fn pair(key: &str, value: &str) -> (String, String) {
+        (key.to_string(), value.to_string())
+    }
+
+    fn error(contents: &str) -> String {
+        parse(contents).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn plain_values() {
+        assert_eq!(
+            parse_pairs("A=1\nB = two words \nC=\nD=a=b"),
+            vec![
+                pair("A", "1"),
+                pair("B", "two words"),
+                pair("C", ""),
+                pair("D", "a=b")
+            ]
+        );
+    }
+
+    #[test]
+    fn comments_and_blank_lines() {
+        assert_eq!(
+            parse_pairs(
+                "# comment\n\n   \n  # indented comment\nA=1 # trailing\nB=x#not-a-comment\n\
+                 C= # empty\nD=#empty"
+            ),
+            vec![
+                pair("A", "1"),
+                pair("B", "x#not-a-comment"),
+                pair("C", ""),
+                pair("D", "")
+            ]
+        );
+    }
+
+    #[test]
+    fn export_prefix() {
+        assert_eq!(
+            parse_pairs("export A=1\nexport\tB=2\nexported=3"),
+            vec![pair("A", "1"), pair("B", "2"), pair("exported", "3")]
+        );
+    }
+
+    #[test]
+    fn single_quoted_values() {
+        assert_eq!(
+            parse_pairs(
+                r#"A='  spaced  '
+B='no \n escapes or $VARS'
+C='# not a comment' # comment
+D='say "hi"'"#
+            ),
+            vec![
+                pair("A", "  spaced  "),
+                pair("B", r"no \n escapes or $VARS"),
+                pair("C", "# not a comment"),
+                pair("D", r#"say "hi""#)
+            ]
+        );
+    }
+
+    #[test]
+    fn double_quoted_values() {
+        assert_eq!(
+            parse_pairs(
+                r#"A="line\nbreak"
+B="tab\there\r"
+C="quote \" backslash \\ dollar \$"
+D="unknown \q escape"
+E="it's # not a comment" # comment"#
+            ),
+            vec![
+                pair("A", "line\nbreak"),
+                pair("B", "tab\there\r"),
+                pair("C", r#"quote " backslash \ dollar $"#),
+                pair("D", r"unknown \q escape"),
+                pair("E", "it's # not a comment")
+            ]
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(error("A=1\nmissing equals"), "line 2: expected KEY=VALUE");
+        assert_eq!(error("1A=x"), "line 1: invalid variable name \"1A\"");
+        assert_eq!(
+            error("MY-VAR=x"),
+            "line 1: invalid variable name \"MY-VAR\""
+        );
+        assert_eq!(error("=x"), "line 1: invalid variable name \"\"");
+        assert_eq!(error("A='open"), "line 1: unterminated single-quoted value");
+        assert_eq!(
+            error("A=\"open"),
+            "line 1: unterminated double-quoted value"
+        );
+        assert_eq!(
+            error("A=\"ends in \\"),
+            "line 1: unterminated double-quoted value"
+        );
+        assert_eq!(
+            error("A='quoted' trailing"),
+            "line 1: unexpected characters after quoted value"
+        );
+    }
+
+    #[test]
+    fn var_names() {
+        assert!(is_valid_var_name("_"));
+        assert!(is_valid_var_name("API_KEY_2"));
+        assert!(!is_valid_var_name(""));
+        assert!(!is_valid_var_name("2FA"));
+        assert!(!is_valid_var_name("A B"));
+    }
+}
@@ -0,0 +1,147 @@
+use std::io::Write;
+use std::path::Path;
+
+/// The shells that `cargo devsecrets` can generate code for.
+#[derive(Clone, Copy)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+impl Shell {
+    pub const NAMES: &'static [&'static str] = &["zsh", "bash", "fish"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "bash" => Some(Shell::Bash),
+            "zsh" => Some(Shell::Zsh),
+            "fish" => Some(Shell::Fish),
+            _ => None,
+        }
+    }
+
+    /// Guesses the user's shell from the `SHELL` environment variable, defaulting to bash.
+    pub fn from_env() -> Self {
+        std::env::var_os("SHELL")
+            .and_then(|shell| {
+                let name = Path::new(&shell).file_name()?.to_str()?.to_string();
+                Shell::from_name(&name)
+            })
+            .unwrap_or(Shell::Bash)
+    }
+}
+
+/// Quotes `value` as a single-quoted POSIX shell word.
+pub fn quote_posix(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+/// Quotes `value` as a single-quoted fish word.
+fn quote_fish(value: &str) -> String {
+    format!("'{}'", value.replace('\\', r"\\").replace('\'', r"\'"))
+}
+
+/// Writes statements that set the given variables when evaluated by `shell`.
+pub fn write_exports(
+    out: &mut impl Write,
+    shell: Shell,
+    vars: &[(String, String)],
+) -> std::io::Result<()> {
+    for (name, value) in vars {
+        match shell {
+            Shell::Bash | Shell::Zsh => writeln!(out, "export {}={}", name, quote_posix(value))?,
+            Shell::Fish => writeln!(out, "set -gx {} {};", name, quote_fish(value))?,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Values that are special to at least one of the supported shells.
+    const TRICKY_VALUES: &[&str] = &[
+        "",
+        "plain",
+        "with spaces",
+        "it's",
+        "''",
+        r#"double "quotes""#,
+        "$HOME and ${HOME}",
+        "`whoami` and $(whoami)",
+        "line one\nline two\n",
+        r"back\slash and \' and \\",
+        "glob * ? [a-z] ~ !history & | ; < > #",
+        "unicode ünïcødé",
+    ];
+
+    fn exports(shell: Shell, name: &str, value: &str) -> String {
+        let mut out = Vec::new();
+        write_exports(&mut out, shell, &[(name.to_string(), value.to_string())]).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn posix_quoting() {
+        assert_eq!(quote_posix("plain"), "'plain'");
+        assert_eq!(quote_posix(""), "''");
+        assert_eq!(quote_posix("it's"), r"'it'\''s'");
+        assert_eq!(quote_posix("$HOME `id` \"x\""), "'$HOME `id` \"x\"'");
+        assert_eq!(quote_posix("a\nb"), "'a\nb'");
+        assert_eq!(quote_posix(r"a\b"), r"'a\b'");
+    }
+
+    #[test]
+    fn fish_quoting() {
+        assert_eq!(quote_fish("plain"), "'plain'");
+        assert_eq!(quote_fish(""), "''");
+        assert_eq!(quote_fish("it's"), r"'it\'s'");
+        assert_eq!(quote_fish(r"a\b"), r"'a\\b'");
+        assert_eq!(quote_fish(r"\'"), r"'\\\''");
+        assert_eq!(quote_fish("$HOME `id` \"x\""), "'$HOME `id` \"x\"'");
+        assert_eq!(quote_fish("a\nb"), "'a\nb'");
+    }
+
+    #[test]
+    fn export_statements() {
+        assert_eq!(exports(Shell::Bash, "A", "it's"), "export A='it'\\''s'\n");
+        assert_eq!(exports(Shell::Zsh, "A", "x"), "export A='x'\n");
+        assert_eq!(exports(Shell::Fish, "A", "it's"), "set -gx A 'it\\'s';\n");
+    }
+
+    /// Evaluates the exports with `shell_binary`, returning the value the shell sees, or `None`
+    /// if the shell is not installed.
+    fn round_trip(shell: Shell, shell_binary: &str, value: &str) -> Option<String> {
+        let script = format!("{}printf '%s' \"$SECRET\"", exports(shell, "SECRET", value));
+        let output = std::process::Command::new(shell_binary)
+            .args(["-c", &script])
+            .output()
+            .ok()?;
+        assert!(
+            output.status.success(),
+            "{} failed: {:?}",
+            shell_binary,
+            output
+        );
+        Some(String::from_utf8(output.stdout).unwrap())
+    }
+
+    #[test]
+    fn shells_read_back_the_exact_value() {
+        let shells = [
+            (Shell::Bash, "sh"),
+            (Shell::Bash, "bash"),
+            (Shell::Zsh, "zsh"),
+            (Shell::Fish, "fish"),
+        ];
+        for &(shell, binary) in &shells {
+            for value in TRICKY_VALUES {
+                if let Some(read_back) = round_trip(shell, binary, value) {
+                    assert_eq!(&read_back, value, "{} changed the value", binary);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,77 @@
+//! Errors reported by the command line tool, and the exit codes they map to.
+
+use std::path::PathBuf;
+
+/// Exit codes returned by `cargo devsecrets`.
+pub mod exit_code {
+    /// An unexpected error, such as an I/O failure.
+    pub const FAILURE: i32 = 1;
+    /// The arguments are invalid, the requested package does not exist, or no package could be
+    /// chosen.
+    pub const USAGE: i32 = 2;
+    /// The cargo workspace could not be located or loaded.
+    pub const WORKSPACE: i32 = 3;
+    /// The package has no devsecrets ID, or its devsecrets directory has not been created.
+    pub const NOT_INITIALIZED: i32 = 4;
+    /// A check (`scan`, `doctor`) ran successfully and found problems.
+    pub const CHECK_FAILED: i32 = 5;
+}
+
+/// Errors with a dedicated exit code. Any other error exits with [`exit_code::FAILURE`].
+#[derive(Debug, thiserror::Error)]
+pub enum CliError {
+    #[error("cargo-devsecrets must be run through cargo, as `cargo devsecrets`")]
+    NotRunByCargo,
+    #[error("Could not load the cargo workspace: {0:#}")]
+    Workspace(anyhow::Error),
+    #[error("No package named `{name}` in the workspace. Available packages: {}", available.join(", "))]
+    PackageNotFound {
+        name: String,
+        available: Vec<String>,
+    },
+    #[error(
+        "{} is a virtual workspace manifest, so a package must be chosen with -p. \
+         Available packages: {}",
+        manifest_path.display(),
+        available.join(", ")
+    )]
+    NoDefaultPackage {
+        manifest_path: PathBuf,
+        available: Vec<String>,
+    },
+    #[error("{} does not exist. Run `cargo devsecrets init` to create it.", .0.display())]
+    MissingId(PathBuf),
+    #[error("The devsecrets directory has not been initialized. Run `cargo devsecrets init`.")]
+    NotInitialized,
+    #[error("{0}")]
+    Usage(String),
+    #[error("{0}")]
+    CheckFailed(String),
+    #[error("{failed} of {total} packages failed")]
+    PackagesFailed {
+        failed: usize,
+        total: usize,
+        exit_code: i32,
+    },
+}
+
+impl CliError {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            CliError::NotRunByCargo | CliError::Workspace(_) => exit_code::WORKSPACE,
+            CliError::PackageNotFound { .. }
+            | CliError::NoDefaultPackage { .. }
+            | CliError::Usage(_) => exit_code::USAGE,
+            CliError::MissingId(_) | CliError::NotInitialized => exit_code::NOT_INITIALIZED,
+            CliError::CheckFailed(_) => exit_code::CHECK_FAILED,
+            CliError::PackagesFailed { exit_code, .. } => *exit_code,
+        }
+    }
+}
+
+/// Returns the exit code for an error returned by a command.
+pub fn exit_code_for(error: &anyhow::Error) -> i32 {
+    error
+        .downcast_ref::<CliError>()
+        .map_or(exit_code::FAILURE, CliError::exit_code)
+}
@@ -0,0 +1,133 @@
+//! Garbage collection of devsecrets directories that are no longer used.
+//!
+//! Collected directories are moved into a trash directory within the devsecrets root rather
+//! than deleted, so that they can be recovered by moving them back.
+
+use crate::projects::{ProjectIndex, ProjectRecord};
+use devsecrets_core::{DevSecretsDir, DevSecretsId, DevSecretsRootDir};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+const TRASH_DIR: &str = ".trash";
+
+/// Empty directories without any recorded projects are only collected once they are at least this
+/// old, since they may belong to a project that was initialized before it was recorded.
+const MIN_EMPTY_AGE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+pub struct GcOptions {
+    /// Only report what would be collected.
+    pub dry_run: bool,
+    /// Only collect directories that have not been modified for at least this long.
+    pub older_than: Option<Duration>,
+}
+
+enum GcReason {
+    Empty,
+    ProjectsMissing,
+}
+
+impl std::fmt::Display for GcReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            GcReason::Empty => write!(f, "empty"),
+            GcReason::ProjectsMissing => write!(f, "recorded projects no longer exist"),
+        }
+    }
+}
+
+/// Returns the most recent modification time of the directory or any file within it.
+fn last_modified(dir: &DevSecretsDir) -> std::io::Result<SystemTime> {
+    let mut latest = dir.path().metadata()?.modified()?;
+    for relpath in dir.files()? {
+        let modified = dir.path().join(relpath).metadata()?.modified()?;
+        latest = std::cmp::max(latest, modified);
+    }
+    Ok(latest)
+}
+
+fn gc_reason(
+    dir: &DevSecretsDir,
+    records: &[ProjectRecord],
+    age: Duration,
+) -> std::io::Result<Option<GcReason>> {
+    // A directory is never garbage while one of its projects still exists, even if it is empty.
+    if records.iter().any(ProjectRecord::exists) {
+        return Ok(None);
+    }
+    let is_empty = dir.files()?.is_empty();
+    if !records.is_empty() {
+        return Ok(Some(if is_empty {
+            GcReason::Empty
+        } else {
+            GcReason::ProjectsMissing
+        }));
+    }
+    // Directories without any recorded projects predate the index, so we can't tell whether
+    // they are still in use. Only collect them if they are empty and have been for a while.
+    if is_empty && age >= MIN_EMPTY_AGE {
+        return Ok(Some(GcReason::Empty));
+    }
+    Ok(None)
+}
+
+fn move_to_trash(
+    trash_dir: &Path,
+    id: &DevSecretsId,
+    dir: &DevSecretsDir,
+    records: &[ProjectRecord],
+) -> anyhow::Result<PathBuf> {
+    std::fs::create_dir_all(trash_dir)?;
+    let target = trash_dir.join(id.id_str());
+    std::fs::rename(dir.path(), &target)?;
+    if !records.is_empty() {
+        // Keep the project records next to the directory, so it's clear where it came from.
+        let records_path = trash_dir.join(format!("{}.projects.json", id.id_str()));
+        std::fs::write(records_path, serde_json::to_vec_pretty(records)?)?;
+    }
+    Ok(target)
+}
+
+/// Finds unused devsecrets directories and moves them to the trash, printing each one.
+pub fn collect_garbage(root: &DevSecretsRootDir, options: &GcOptions) -> anyhow::Result<()> {
+    let mut index = ProjectIndex::load(root)?;
+    let now = SystemTime::now();
+    let timestamp = humantime::format_rfc3339_seconds(now)
+        .to_string()
+        .replace(':', "-");
+    let trash_dir = root.path().join(TRASH_DIR).join(timestamp);
+
+    let mut collected = 0;
+    for (id, dir) in root.children()? {
+        let age = now.duration_since(last_modified(&dir)?).unwrap_or_default();
+        let reason = match gc_reason(&dir, index.projects(&id), age)? {
+            Some(reason) => reason,
+            None => continue,
+        };
+        if options
+            .older_than
+            .is_some_and(|older_than| age < older_than)
+        {
+            continue;
+        }
+
+        collected += 1;
+        if options.dry_run {
+            println!("Would collect {} ({})", id.id_str(), reason);
+            continue;
+        }
+        let target = move_to_trash(&trash_dir, &id, &dir, index.projects(&id))?;
+        index.remove(&id);
+        index.save(root)?;
+        println!(
+            "Collected {} ({}) to {}",
+            id.id_str(),
+            reason,
+            target.display()
+        );
+    }
+
+    if collected == 0 {
+        println!("Nothing to collect");
+    }
+    Ok(())
+}
@@ -0,0 +1,88 @@
+//! Installs a git pre-commit hook that blocks commits containing secret values.
+
+use crate::env::quote_posix;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// A line included in hooks we write, so we can recognize them later.
+const HOOK_MARKER: &str = "# Installed by cargo-devsecrets.";
+/// The name an existing pre-commit hook is moved to, so that our hook can chain into it.
+const CHAINED_HOOK_NAME: &str = "pre-commit.pre-devsecrets";
+
+fn git_path(dir: &Path, args: &[&str]) -> anyhow::Result<PathBuf> {
+    let output = Command::new("git").args(args).current_dir(dir).output()?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "The package is not in a git repository: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(dir.join(String::from_utf8(output.stdout)?.trim()))
+}
+
+fn hook_script(manifest_relpath: &Path, chained: bool) -> String {
+    let mut script = format!("#!/bin/sh\n{}\n", HOOK_MARKER);
+    if chained {
+        script.push_str(&format!(
+            "\"$(dirname \"$0\")/{}\" \"$@\" || exit $?\n",
+            CHAINED_HOOK_NAME
+        ));
+    }
+    // Hooks run from the top level of the working tree, so the manifest path is relative to
+    // that.
+    script.push_str(&format!(
+        "exec cargo devsecrets --manifest-path {} scan --staged\n",
+        quote_posix(&manifest_relpath.to_string_lossy().replace('\\', "/"))
+    ));
+    script
+}
+
+#[cfg(unix)]
+fn make_executable(path: &Path) -> std::io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755))
+}
+
+#[cfg(not(unix))]
+fn make_executable(_path: &Path) -> std::io::Result<()> {
+    Ok(())
+}
+
+/// Installs the pre-commit hook for the repository containing `manifest_path`.
+///
+/// If the repository already has a pre-commit hook that was not written by us, it is renamed
+/// and called by the new hook before scanning. Returns the path of the installed hook.
+pub fn install_hook(manifest_path: &Path) -> anyhow::Result<PathBuf> {
+    let manifest_dir = manifest_path
+        .parent()
+        .expect("Manifest path must have a parent");
+    let top_level = git_path(manifest_dir, &["rev-parse", "--show-toplevel"])?;
+    let hooks_dir = git_path(manifest_dir, &["rev-parse", "--git-path", "hooks"])?;
+    let manifest_relpath = manifest_path
+        .strip_prefix(&top_level)
+        .map_err(|_| anyhow::anyhow!("{:?} is not within {:?}", manifest_path, top_level))?;
+
+    std::fs::create_dir_all(&hooks_dir)?;
+    let hook_path = hooks_dir.join("pre-commit");
+    let chained_path = hooks_dir.join(CHAINED_HOOK_NAME);
+    if hook_path.exists() {
+        let existing = std::fs::read_to_string(&hook_path).unwrap_or_default();
+        if !existing.contains(HOOK_MARKER) {
+            if chained_path.exists() {
+                anyhow::bail!(
+                    "Both {:?} and {:?} exist. Merge them by hand and try again.",
+                    hook_path,
+                    chained_path
+                );
+            }
+            std::fs::rename(&hook_path, &chained_path)?;
+        }
+    }
+
+    std::fs::write(
+        &hook_path,
+        hook_script(manifest_relpath, chained_path.exists()),
+    )?;
+    make_executable(&hook_path)?;
+    Ok(hook_path)
+}
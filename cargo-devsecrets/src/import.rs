@@ -0,0 +1,272 @@
+use crate::dotenv;
+use devsecrets_core::DevSecretsDir;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+pub struct ImportOptions {
+    /// The directory within the devsecrets directory to import into.
+    pub dest: PathBuf,
+    /// Split a dotenv source file into one file per variable.
+    pub split_dotenv: bool,
+    /// Overwrite files that already exist in the devsecrets directory.
+    pub force: bool,
+    /// Securely delete the source files once they have been imported.
+    pub remove_originals: bool,
+}
+
+enum Contents {
+    Copy(PathBuf),
+    Value(String),
+}
+
+/// A single file to be written into the devsecrets directory.
+struct ImportItem {
+    relpath: PathBuf,
+    contents: Contents,
+}
+
+fn collect_dir_items(
+    source: &Path,
+    dest: &Path,
+    items: &mut Vec<ImportItem>,
+) -> anyhow::Result<()> {
+    for entry in std::fs::read_dir(source)? {
+        let entry = entry?;
+        let relpath = dest.join(entry.file_name());
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            collect_dir_items(&entry.path(), &relpath, items)?;
+        } else if file_type.is_symlink() && entry.path().is_dir() {
+            // Following these could import files from anywhere, or loop forever.
+            anyhow::bail!(
+                "{:?} is a symlink to a directory, which can't be imported. Import its target \
+                 directly instead.",
+                entry.path()
+            );
+        } else {
+            items.push(ImportItem {
+                relpath,
+                contents: Contents::Copy(entry.path()),
+            });
+        }
+    }
+    Ok(())
+}
+
+fn plan_import(source: &Path, options: &ImportOptions) -> anyhow::Result<Vec<ImportItem>> {
+    let mut items = Vec::new();
+    if options.split_dotenv {
+        if !source.is_file() {
+            anyhow::bail!("{:?} must be a file to be split as a dotenv file", source);
+        }
+        let contents = std::fs::read_to_string(source)?;
+        let entries = dotenv::parse(&contents)
+            .map_err(|e| e.context(format!("In dotenv file {:?}", source)))?;
+        for (key, value) in entries {
+            items.push(ImportItem {
+                relpath: options.dest.join(key),
+                contents: Contents::Value(value),
+            });
+        }
+    } else if source.is_dir() {
+        collect_dir_items(source, &options.dest, &mut items)?;
+        items.sort_by(|a, b| a.relpath.cmp(&b.relpath));
+    } else {
+        let file_name = match source.file_name() {
+            Some(name) => name,
+            None => anyhow::bail!("Could not determine the file name of {:?}", source),
+        };
+        items.push(ImportItem {
+            relpath: options.dest.join(file_name),
+            contents: Contents::Copy(source.to_path_buf()),
+        });
+    }
+
+    for item in &items {
+        devsecrets_core::check_relative_path(&item.relpath).map_err(anyhow::Error::msg)?;
+    }
+    Ok(items)
+}
+
+/// Overwrites a file with zeros before removing it, so that its contents are not trivially
+/// recoverable.
+///
+/// This is a best-effort measure: copy-on-write filesystems and SSD wear leveling may still
+/// retain the original data.
+fn secure_remove_file(path: &Path) -> std::io::Result<()> {
+    let len = path.metadata()?.len();
+    let mut file = std::fs::OpenOptions::new().write(true).open(path)?;
+    let zeros = [0u8; 4096];
+    let mut remaining = len;
+    while remaining > 0 {
+        let chunk = std::cmp::min(remaining, zeros.len() as u64) as usize;
+        file.write_all(&zeros[..chunk])?;
+        remaining -= chunk as u64;
+    }
+    file.sync_all()?;
+    drop(file);
+    std::fs::remove_file(path)
+}
+
+/// Returns the symbolic links at or within `path`.
+fn find_symlinks(path: &Path, links: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    let file_type = path.symlink_metadata()?.file_type();
+    if file_type.is_symlink() {
+        links.push(path.to_path_buf());
+    } else if file_type.is_dir() {
+        for entry in std::fs::read_dir(path)? {
+            find_symlinks(&entry?.path(), links)?;
+        }
+    }
+    Ok(())
+}
+
+fn secure_remove(path: &Path) -> std::io::Result<()> {
+    let file_type = path.symlink_metadata()?.file_type();
+    if file_type.is_dir() {
+        for entry in std::fs::read_dir(path)? {
+            secure_remove(&entry?.path())?;
+        }
+        std::fs::remove_dir(path)
+    } else if file_type.is_symlink() {
+        // Only the link itself is removed, never the file it points to.
+        std::fs::remove_file(path)
+    } else {
+        secure_remove_file(path)
+    }
+}
+
+/// Imports the file or directory at `source` into the devsecrets directory, returning the
+/// relative paths of the files that were written.
+pub fn import(
+    dir: &DevSecretsDir,
+    source: &Path,
+    options: &ImportOptions,
+) -> anyhow::Result<Vec<PathBuf>> {
+    devsecrets_core::check_relative_path(&options.dest).map_err(anyhow::Error::msg)?;
+    let items = plan_import(source, options)?;
+
+    if options.remove_originals {
+        // Links are imported by reading the file they point to, but removing them would only
+        // remove the link, leaving the secret on disk.
+        let mut links = Vec::new();
+        find_symlinks(source, &mut links)?;
+        if !links.is_empty() {
+            anyhow::bail!(
+                "--remove-originals can't remove the files behind these symbolic links, so \
+                 nothing was imported. Import the files they point to instead: {}",
+                links
+                    .iter()
+                    .map(|link| link.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+        }
+    }
+
+    if !options.force {
+        let existing: Vec<_> = items
+            .iter()
+            .filter(|item| dir.path().join(&item.relpath).exists())
+            .map(|item| item.relpath.display().to_string())
+            .collect();
+        if !existing.is_empty() {
+            anyhow::bail!(
+                "These files already exist in the devsecrets directory (use --force to \
+                 overwrite): {}",
+                existing.join(", ")
+            );
+        }
+    }
+
+    for item in &items {
+        let target = dir.path().join(&item.relpath);
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        // Copy the contents rather than the file, so the source's permissions aren't kept.
+        let mut file = devsecrets_core::create_private_file(&target)?;
+        match &item.contents {
+            Contents::Copy(path) => {
+                std::fs::File::open(path)
+                    .and_then(|mut source| std::io::copy(&mut source, &mut file))
+                    .map_err(|e| anyhow::anyhow!("Could not copy {:?}: {}", path, e))?;
+            }
+            Contents::Value(value) => file.write_all(value.as_bytes())?,
+        }
+    }
+
+    if options.remove_originals {
+        secure_remove(source)
+            .map_err(|e| anyhow::anyhow!("Could not remove {:?}: {}", source, e))?;
+    }
+
+    Ok(items.into_iter().map(|item| item.relpath).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A temporary directory that is removed when dropped.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new() -> Self {
+            let path = std::env::temp_dir().join(format!(
+                "devsecrets-import-test-{}",
+                devsecrets_core::DevSecretsId::new_unique().id_str()
+            ));
+            std::fs::create_dir_all(&path).unwrap();
+            TempDir(path)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn options() -> ImportOptions {
+        ImportOptions {
+            dest: PathBuf::from("imported"),
+            split_dotenv: false,
+            force: false,
+            remove_originals: false,
+        }
+    }
+
+    #[test]
+    fn directories_are_imported_recursively() {
+        let tmp = TempDir::new();
+        std::fs::create_dir_all(tmp.0.join("nested")).unwrap();
+        std::fs::write(tmp.0.join("top.txt"), "top").unwrap();
+        std::fs::write(tmp.0.join("nested/inner.txt"), "inner").unwrap();
+
+        let items = plan_import(&tmp.0, &options()).unwrap();
+        let relpaths: Vec<_> = items.iter().map(|item| item.relpath.as_path()).collect();
+        assert_eq!(
+            relpaths,
+            [
+                Path::new("imported/nested/inner.txt"),
+                Path::new("imported/top.txt")
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_to_directories_are_rejected() {
+        let tmp = TempDir::new();
+        std::fs::create_dir_all(tmp.0.join("source")).unwrap();
+        std::fs::create_dir_all(tmp.0.join("elsewhere")).unwrap();
+        std::fs::write(tmp.0.join("elsewhere/secret.txt"), "secret").unwrap();
+        std::os::unix::fs::symlink(tmp.0.join("elsewhere"), tmp.0.join("source/link")).unwrap();
+
+        let error = plan_import(&tmp.0.join("source"), &options())
+            .err()
+            .unwrap();
+        assert!(error.to_string().contains("is a symlink to a directory"));
+    }
+}
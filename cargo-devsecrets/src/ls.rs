@@ -0,0 +1,169 @@
+use devsecrets_core::DevSecretsDir;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+#[derive(Serialize)]
+struct FileEntry {
+    path: PathBuf,
+    size: u64,
+    #[serde(serialize_with = "serialize_time")]
+    modified: Option<SystemTime>,
+    format: &'static str,
+}
+
+fn serialize_time<S: serde::Serializer>(
+    time: &Option<SystemTime>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match time {
+        Some(time) => serializer.collect_str(&humantime::format_rfc3339_seconds(*time)),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// Guesses the format of a secret file from its extension, falling back to
+/// sniffing its contents.
+fn detect_format(path: &Path) -> std::io::Result<&'static str> {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("json") => return Ok("json"),
+        Some("toml") => return Ok("toml"),
+        Some("env") => return Ok("dotenv"),
+        _ => (),
+    }
+    if path.file_name() == Some(".env".as_ref()) {
+        return Ok("dotenv");
+    }
+
+    let contents = std::fs::read(path)?;
+    Ok(if std::str::from_utf8(&contents).is_ok() {
+        "text"
+    } else {
+        "binary"
+    })
+}
+
+fn read_entries(dir: &DevSecretsDir) -> anyhow::Result<Vec<FileEntry>> {
+    let mut entries = Vec::new();
+    for relpath in dir.files()? {
+        let fullpath = dir.path().join(&relpath);
+        let metadata = fullpath.metadata()?;
+        entries.push(FileEntry {
+            size: metadata.len(),
+            modified: metadata.modified().ok(),
+            format: detect_format(&fullpath)?,
+            path: relpath,
+        });
+    }
+    Ok(entries)
+}
+
+fn format_time(time: Option<SystemTime>) -> String {
+    match time {
+        Some(time) => humantime::format_rfc3339_seconds(time).to_string(),
+        None => "-".to_string(),
+    }
+}
+
+fn write_table(out: &mut impl Write, entries: &[FileEntry]) -> std::io::Result<()> {
+    let size_width = entries
+        .iter()
+        .map(|e| e.size.to_string().len())
+        .max()
+        .unwrap_or(0);
+    for entry in entries {
+        writeln!(
+            out,
+            "{:>size_width$}  {}  {:<6}  {}",
+            entry.size,
+            format_time(entry.modified),
+            entry.format,
+            entry.path.display(),
+            size_width = size_width,
+        )?;
+    }
+    Ok(())
+}
+
+#[derive(Default)]
+struct TreeNode<'a> {
+    children: BTreeMap<String, TreeNode<'a>>,
+    entry: Option<&'a FileEntry>,
+}
+
+fn write_tree_children(out: &mut impl Write, node: &TreeNode, prefix: &str) -> std::io::Result<()> {
+    let count = node.children.len();
+    for (i, (name, child)) in node.children.iter().enumerate() {
+        let last = i + 1 == count;
+        let (branch, indent) = if last {
+            ("└── ", "    ")
+        } else {
+            ("├── ", "│   ")
+        };
+        match child.entry {
+            Some(entry) => writeln!(
+                out,
+                "{}{}{} ({} bytes, {})",
+                prefix, branch, name, entry.size, entry.format
+            )?,
+            None => writeln!(out, "{}{}{}/", prefix, branch, name)?,
+        }
+        write_tree_children(out, child, &format!("{}{}", prefix, indent))?;
+    }
+    Ok(())
+}
+
+fn write_tree(out: &mut impl Write, entries: &[FileEntry]) -> std::io::Result<()> {
+    let mut root = TreeNode::default();
+    for entry in entries {
+        let mut node = &mut root;
+        for component in entry.path.iter() {
+            node = node
+                .children
+                .entry(component.to_string_lossy().into_owned())
+                .or_default();
+        }
+        node.entry = Some(entry);
+    }
+    writeln!(out, ".")?;
+    write_tree_children(out, &root, "")
+}
+
+#[derive(Clone, Copy)]
+pub enum ListStyle {
+    Table,
+    Tree,
+    Json,
+}
+
+/// Lists the files in the given devsecrets directory to stdout.
+pub fn list_files(dir: &DevSecretsDir, style: ListStyle) -> anyhow::Result<()> {
+    let entries = read_entries(dir)?;
+    let stdout = std::io::stdout();
+    let mut out = stdout.lock();
+    match style {
+        ListStyle::Table => write_table(&mut out, &entries)?,
+        ListStyle::Tree => write_tree(&mut out, &entries)?,
+        ListStyle::Json => {
+            serde_json::to_writer_pretty(&mut out, &entries)?;
+            writeln!(out)?;
+        }
+    }
+    Ok(())
+}
+
+/// Lists the files in the devsecrets directories of several packages to stdout, as a JSON object
+/// keyed by package name.
+pub fn list_package_files_json(dirs: &[(&str, DevSecretsDir)]) -> anyhow::Result<()> {
+    let mut listing = BTreeMap::new();
+    for (name, dir) in dirs {
+        listing.insert(*name, read_entries(dir)?);
+    }
+    let stdout = std::io::stdout();
+    let mut out = stdout.lock();
+    serde_json::to_writer_pretty(&mut out, &listing)?;
+    writeln!(out)?;
+    Ok(())
+}
@@ -1,71 +1,559 @@
+use anyhow::Context;
+use devsecrets_core::{IdSource, DEVSECRETS_ID_FILE};
+use error::CliError;
 use std::path::{Path, PathBuf};
+use workspace::PackageSelection;
 
+mod bundle;
 mod cli;
+mod doctor;
+mod dotenv;
+mod env;
+mod error;
+mod gc;
+mod hook;
+mod import;
+mod ls;
+mod manifest;
+mod projects;
+mod run;
+mod scan;
+mod secret_env;
 mod workspace;
 
-pub fn init_devsecrets_dir_from_manifest_dir(
-    manifest_dir: impl AsRef<Path>,
+/// Returns the package's own ID, storing it in the package manifest if it is not there already.
+///
+/// The ID of an existing ID file is moved into the manifest, and the file is removed so that it
+/// doesn't take precedence. A new ID is created rather than copying one shared by the workspace.
+fn ensure_manifest_id(
+    package: &cargo_metadata::Package,
+) -> anyhow::Result<devsecrets_core::DevSecretsId> {
+    let located = devsecrets_core::locate_devsecrets_id(manifest_dir(package))?;
+    let (id, id_file) = match located {
+        Some(located) => match located.source {
+            IdSource::PackageManifest(_) => return Ok(located.id),
+            IdSource::PackageFile(path) => (located.id, Some(path)),
+            IdSource::WorkspaceFile(_) | IdSource::WorkspaceManifest(_) => {
+                (devsecrets_core::DevSecretsId::new_unique(), None)
+            }
+        },
+        None => (devsecrets_core::DevSecretsId::new_unique(), None),
+    };
+    manifest::write_manifest_id(&package.manifest_path, &id)?;
+    if let Some(id_file) = id_file {
+        std::fs::remove_file(&id_file)
+            .with_context(|| format!("Unable to remove {:?}", id_file))?;
+        eprintln!(
+            "note: moved the ID from {} to {}",
+            id_file.display(),
+            package.manifest_path.display()
+        );
+    }
+    Ok(id)
+}
+
+/// Initializes the devsecrets directory for `package`, creating an ID if needed. The ID is
+/// written to an ID file, or to the package manifest if `in_manifest` is set.
+pub fn init_devsecrets_dir_for_package(
+    package: &cargo_metadata::Package,
+    in_manifest: bool,
 ) -> anyhow::Result<PathBuf> {
-    let id = devsecrets_core::ensure_devsecrets_id(manifest_dir)?;
+    let id = if in_manifest {
+        ensure_manifest_id(package)?
+    } else {
+        devsecrets_core::ensure_devsecrets_id(manifest_dir(package))?
+    };
     let root = devsecrets_core::DevSecretsRootDir::ensure_new()?;
     let child = root.ensure_child(&id)?;
+    projects::record_project(&root, &id, &package.name, &package.manifest_path)?;
+    Ok(child.path().to_path_buf())
+}
+
+/// Creates an ID file in the workspace root that is shared by all members without their own ID
+/// file, and records those members as projects using it.
+fn init_workspace_shared(workspace: &workspace::CargoWorkspace) -> anyhow::Result<PathBuf> {
+    let id = devsecrets_core::ensure_devsecrets_id(workspace.root_dir())?;
+    let root = devsecrets_core::DevSecretsRootDir::ensure_new()?;
+    let child = root.ensure_child(&id)?;
+    for package in workspace.members() {
+        let located = devsecrets_core::locate_devsecrets_id(manifest_dir(package))?;
+        match located {
+            Some(located) if located.id.id_str() == id.id_str() => {
+                projects::record_project(&root, &id, &package.name, &package.manifest_path)?
+            }
+            Some(located) => eprintln!(
+                "warning: {} uses its own ID from {}, which takes precedence over the shared ID",
+                package.name,
+                located.source.path().display()
+            ),
+            None => eprintln!(
+                "warning: {} is not within the workspace root, so it does not use the shared ID",
+                package.name
+            ),
+        }
+    }
     Ok(child.path().to_path_buf())
 }
 
-pub fn get_devsecrets_dir_from_manifest_dir(
+/// Reads the package's devsecrets ID, failing if it has not been initialized.
+fn read_id(manifest_dir: &Path) -> anyhow::Result<devsecrets_core::DevSecretsId> {
+    devsecrets_core::read_devsecrets_id(manifest_dir)?
+        .ok_or_else(|| CliError::MissingId(manifest_dir.join(DEVSECRETS_ID_FILE)).into())
+}
+
+pub fn open_devsecrets_dir_from_manifest_dir(
     manifest_dir: impl AsRef<Path>,
-) -> anyhow::Result<Option<PathBuf>> {
-    let id = devsecrets_core::read_devsecrets_id(manifest_dir)?
-        .ok_or(anyhow::anyhow!("Could not read devsecrets id from project"))?;
+) -> anyhow::Result<Option<devsecrets_core::DevSecretsDir>> {
+    let id = read_id(manifest_dir.as_ref())?;
     let root = match devsecrets_core::DevSecretsRootDir::new()? {
         Some(root) => root,
         None => return Ok(None),
     };
 
-    let child = match root.get_child(&id)? {
-        Some(child) => child,
-        None => return Ok(None),
+    Ok(root.get_child(&id)?)
+}
+
+fn require_devsecrets_dir_from_manifest_dir(
+    manifest_dir: impl AsRef<Path>,
+) -> anyhow::Result<devsecrets_core::DevSecretsDir> {
+    Ok(open_devsecrets_dir_from_manifest_dir(manifest_dir)?.ok_or(CliError::NotInitialized)?)
+}
+
+fn env_mapping_from_matches(
+    package: &cargo_metadata::Package,
+    matches: &clap::ArgMatches,
+) -> anyhow::Result<secret_env::EnvMapping> {
+    let dotenv = if matches.is_present("no-dotenv") {
+        secret_env::DotenvSource::Disabled
+    } else {
+        match matches.value_of_os("dotenv") {
+            Some(path) => secret_env::DotenvSource::File(path.into()),
+            None => secret_env::DotenvSource::Default,
+        }
     };
-    Ok(Some(child.path().to_path_buf()))
+    let mut vars = secret_env::configured_vars(package)?;
+    for arg in matches.values_of("env").into_iter().flatten() {
+        vars.push(secret_env::parse_var_arg(arg)?);
+    }
+    Ok(secret_env::EnvMapping { dotenv, vars })
+}
+
+fn resolve_env_vars(
+    package: &cargo_metadata::Package,
+    manifest_dir: &Path,
+    matches: &clap::ArgMatches,
+) -> anyhow::Result<Vec<(String, String)>> {
+    let dir = require_devsecrets_dir_from_manifest_dir(manifest_dir)?;
+    env_mapping_from_matches(package, matches)?.resolve(&dir)
+}
+
+fn export_bundle(manifest_dir: &Path, matches: &clap::ArgMatches) -> anyhow::Result<usize> {
+    let id = read_id(manifest_dir)?;
+    let dir = require_devsecrets_dir_from_manifest_dir(manifest_dir)?;
+    let key = match matches.values_of("recipient") {
+        Some(recipients) => bundle::BundleKey::Recipients(
+            recipients
+                .map(bundle::parse_recipient)
+                .collect::<anyhow::Result<_>>()?,
+        ),
+        None => bundle::BundleKey::Passphrase(bundle::read_passphrase(true)?),
+    };
+    let output = Path::new(matches.value_of_os("OUTPUT").expect("OUTPUT is required"));
+    bundle::export_bundle(&dir, &id, output, key)
+}
+
+fn import_bundle(
+    package: &cargo_metadata::Package,
+    manifest_dir: &Path,
+    matches: &clap::ArgMatches,
+) -> anyhow::Result<bundle::ImportedBundle> {
+    let id = devsecrets_core::read_devsecrets_id(manifest_dir)?;
+    let root = devsecrets_core::DevSecretsRootDir::ensure_new()?;
+    let identities = bundle::BundleIdentities {
+        identity_files: matches
+            .values_of_os("identity")
+            .into_iter()
+            .flatten()
+            .map(PathBuf::from)
+            .collect(),
+        passphrase: || bundle::read_passphrase(false),
+    };
+    let path = Path::new(matches.value_of_os("BUNDLE").expect("BUNDLE is required"));
+    let imported = bundle::import_bundle(
+        &root,
+        path,
+        &identities,
+        id.as_ref(),
+        matches.is_present("force"),
+    )?;
+    if id.is_some() {
+        projects::record_project(&root, &imported.id, &package.name, &package.manifest_path)?;
+    }
+    Ok(imported)
+}
+
+fn list_projects(json: bool) -> anyhow::Result<()> {
+    match devsecrets_core::DevSecretsRootDir::new()? {
+        Some(root) => projects::list_projects(&root, json),
+        None => Ok(()),
+    }
+}
+
+fn collect_garbage(matches: &clap::ArgMatches) -> anyhow::Result<()> {
+    let older_than = match matches.value_of("older-than") {
+        Some(age) => Some(
+            humantime::parse_duration(age)
+                .map_err(|e| anyhow::anyhow!("Invalid age {:?}: {}", age, e))?,
+        ),
+        None => None,
+    };
+    let options = gc::GcOptions {
+        dry_run: matches.is_present("dry-run"),
+        older_than,
+    };
+    match devsecrets_core::DevSecretsRootDir::new()? {
+        Some(root) => gc::collect_garbage(&root, &options),
+        None => Ok(()),
+    }
+}
+
+/// Scans the repository containing the package for secret values, failing if any were found.
+fn scan_for_secrets(manifest_dir: &Path, matches: &clap::ArgMatches) -> anyhow::Result<()> {
+    let min_length: usize = matches
+        .value_of("min-length")
+        .expect("min-length has a default")
+        .parse()
+        .map_err(|e| anyhow::anyhow!("Invalid --min-length: {}", e))?;
+    let staged = matches.is_present("staged");
+    let dir = match open_devsecrets_dir_from_manifest_dir(manifest_dir) {
+        Ok(Some(dir)) => dir,
+        // The pre-commit hook runs in every clone, including ones where `init` hasn't been run.
+        // Without any secrets there is nothing to find, so don't block the commit.
+        Ok(None) if staged => {
+            eprintln!("warning: The devsecrets directory has not been initialized. Skipping scan.");
+            return Ok(());
+        }
+        Err(e) if staged && matches!(e.downcast_ref(), Some(CliError::MissingId(_))) => {
+            eprintln!("warning: {:#} Skipping scan.", e);
+            return Ok(());
+        }
+        Ok(None) => return Err(CliError::NotInitialized.into()),
+        Err(e) => return Err(e),
+    };
+    let secrets = scan::collect_secret_values(&dir, min_length)?;
+
+    let git_root = scan::git_root(manifest_dir);
+    let root = git_root.as_deref().unwrap_or(manifest_dir);
+    let mut findings = Vec::new();
+    if staged {
+        if git_root.is_none() {
+            anyhow::bail!("--staged requires the package to be in a git repository");
+        }
+        scan::scan_staged(root, &secrets, &mut findings)?;
+        if !scan::report(&findings) {
+            return Err(CliError::CheckFailed(
+                "Commit blocked: the staged changes contain secret values. Remove them and \
+                 stage the files again, or use `git commit --no-verify` to commit anyway."
+                    .to_string(),
+            )
+            .into());
+        }
+        return Ok(());
+    }
+
+    scan::scan_working_tree(root, git_root.is_some(), &secrets, &mut findings)?;
+    if matches.is_present("history") {
+        if git_root.is_none() {
+            anyhow::bail!("--history requires the package to be in a git repository");
+        }
+        scan::scan_history(root, &secrets, &mut findings)?;
+    }
+    if !scan::report(&findings) {
+        return Err(CliError::CheckFailed("Secret values were found".to_string()).into());
+    }
+    Ok(())
+}
+
+fn run_command(
+    package: &cargo_metadata::Package,
+    manifest_dir: &Path,
+    matches: &clap::ArgMatches,
+) -> anyhow::Result<()> {
+    let vars = resolve_env_vars(package, manifest_dir, matches)?;
+    let mut command = matches
+        .values_of_os("COMMAND")
+        .expect("COMMAND is required")
+        .map(|arg| arg.to_os_string());
+    let program = command.next().expect("COMMAND has at least one value");
+    let args: Vec<_> = command.collect();
+    run::run_with_env(&program, &args, &vars)
+}
+
+fn manifest_dir(package: &cargo_metadata::Package) -> &Path {
+    package
+        .manifest_path
+        .parent()
+        .expect("Manifest path must have a parent")
+}
+
+fn write_completions(matches: &clap::ArgMatches) {
+    let shell = matches.value_of("SHELL").expect("SHELL is required");
+    cli::build_cli().gen_completions_to(
+        "cargo",
+        shell.parse().expect("clap validates shell names"),
+        &mut std::io::stdout(),
+    );
+}
+
+/// Runs a subcommand that does not act on a particular package.
+fn run_global_command(
+    name: &str,
+    matches: &clap::ArgMatches,
+    global: &clap::ArgMatches,
+) -> anyhow::Result<()> {
+    match name {
+        "projects" => list_projects(matches.is_present("json")).context("Unable to list projects"),
+        "gc" => collect_garbage(matches).context("Unable to collect garbage"),
+        "doctor" => {
+            let healthy = doctor::run_doctor(
+                global.value_of_os("manifest-path").map(Path::new),
+                &package_selection(global, matches)?,
+            );
+            if !healthy {
+                return Err(CliError::CheckFailed("Some checks failed".to_string()).into());
+            }
+            Ok(())
+        }
+        "completions" => {
+            write_completions(matches);
+            Ok(())
+        }
+        _ => unreachable!("Unhandled subcommand {:?}", name),
+    }
+}
+
+/// Runs a subcommand that acts on `package`.
+fn run_package_command(
+    package: &cargo_metadata::Package,
+    name: &str,
+    matches: &clap::ArgMatches,
+) -> anyhow::Result<()> {
+    let manifest_dir = manifest_dir(package);
+    match name {
+        "init" => {
+            let dir = init_devsecrets_dir_for_package(package, matches.is_present("in-manifest"))
+                .context("Unable to init directory")?;
+            println!("Dir: {}", dir.display());
+        }
+        "path" => {
+            let dir = require_devsecrets_dir_from_manifest_dir(manifest_dir)
+                .context("Unable to find devsecrets directory")?;
+            println!("{}", dir.path().display());
+        }
+        "ls" => {
+            let style = if matches.is_present("json") {
+                ls::ListStyle::Json
+            } else if matches.is_present("tree") {
+                ls::ListStyle::Tree
+            } else {
+                ls::ListStyle::Table
+            };
+            require_devsecrets_dir_from_manifest_dir(manifest_dir)
+                .and_then(|dir| ls::list_files(&dir, style))
+                .context("Unable to list devsecrets")?;
+        }
+        "run" => run_command(package, manifest_dir, matches).context("Unable to run command")?,
+        "env" => {
+            let shell = match matches.value_of("shell") {
+                Some(name) => env::Shell::from_name(name).expect("clap validates shell names"),
+                None => env::Shell::from_env(),
+            };
+            let vars = resolve_env_vars(package, manifest_dir, matches)
+                .context("Unable to export variables")?;
+            env::write_exports(&mut std::io::stdout(), shell, &vars)?;
+        }
+        "import" => {
+            let source = Path::new(matches.value_of_os("SOURCE").expect("SOURCE is required"));
+            let options = import::ImportOptions {
+                dest: matches.value_of_os("to").unwrap_or_default().into(),
+                split_dotenv: matches.is_present("split-dotenv"),
+                force: matches.is_present("force"),
+                remove_originals: matches.is_present("remove-originals"),
+            };
+            let files = require_devsecrets_dir_from_manifest_dir(manifest_dir)
+                .and_then(|dir| import::import(&dir, source, &options))
+                .context("Unable to import secrets")?;
+            for file in files {
+                println!("Imported {}", file.display());
+            }
+        }
+        "scan" => scan_for_secrets(manifest_dir, matches).context("Unable to scan for secrets")?,
+        "install-hook" => {
+            let path =
+                hook::install_hook(&package.manifest_path).context("Unable to install hook")?;
+            println!("Installed {}", path.display());
+        }
+        "export" => {
+            let count = export_bundle(manifest_dir, matches).context("Unable to export secrets")?;
+            println!("Exported {} files", count);
+        }
+        "import-bundle" => {
+            let imported =
+                import_bundle(package, manifest_dir, matches).context("Unable to import bundle")?;
+            for file in &imported.files {
+                println!("Imported {}", file.display());
+            }
+            println!("Dir: {}", imported.dir.path().display());
+        }
+        _ => unreachable!("Unhandled subcommand {:?}", name),
+    }
+    Ok(())
+}
+
+/// Returns the packages chosen by `-p` and the subcommand's `--workspace` and `--exclude` args.
+fn package_selection<'a>(
+    global: &'a clap::ArgMatches<'a>,
+    matches: &'a clap::ArgMatches<'a>,
+) -> Result<PackageSelection<'a>, CliError> {
+    match (global.value_of("package"), matches.is_present("workspace")) {
+        (Some(_), true) => Err(CliError::Usage(
+            "--package cannot be used with --workspace".to_string(),
+        )),
+        (Some(name), false) => Ok(PackageSelection::Named(name)),
+        (None, true) => Ok(PackageSelection::Workspace {
+            exclude: matches.values_of("exclude").into_iter().flatten().collect(),
+        }),
+        (None, false) => Ok(PackageSelection::Default),
+    }
+}
+
+/// Runs `command` for each package, reporting failures on stderr without stopping.
+fn for_each_package<'a>(
+    packages: &[&'a cargo_metadata::Package],
+    mut command: impl FnMut(&'a cargo_metadata::Package) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    let mut failures = Vec::new();
+    for package in packages {
+        if let Err(e) = command(package) {
+            eprintln!("error: {}: {:#}", package.name, e);
+            failures.push(error::exit_code_for(&e));
+        }
+    }
+    match failures.first() {
+        None => Ok(()),
+        Some(&first) => Err(CliError::PackagesFailed {
+            failed: failures.len(),
+            total: packages.len(),
+            exit_code: if failures.iter().all(|&code| code == first) {
+                first
+            } else {
+                error::exit_code::FAILURE
+            },
+        }
+        .into()),
+    }
+}
+
+/// Runs a subcommand that was given `--workspace` on each of `packages`.
+fn run_workspace_command(
+    packages: &[&cargo_metadata::Package],
+    name: &str,
+    matches: &clap::ArgMatches,
+) -> anyhow::Result<()> {
+    match name {
+        "init" => for_each_package(packages, |package| {
+            let dir = init_devsecrets_dir_for_package(package, matches.is_present("in-manifest"))?;
+            println!("{}: {}", package.name, dir.display());
+            Ok(())
+        }),
+        "path" => for_each_package(packages, |package| {
+            let dir = require_devsecrets_dir_from_manifest_dir(manifest_dir(package))?;
+            println!("{}\t{}", package.name, dir.path().display());
+            Ok(())
+        }),
+        "ls" if matches.is_present("json") => {
+            let mut dirs = Vec::new();
+            let result = for_each_package(packages, |package| {
+                let dir = require_devsecrets_dir_from_manifest_dir(manifest_dir(package))?;
+                dirs.push((package.name.as_str(), dir));
+                Ok(())
+            });
+            ls::list_package_files_json(&dirs)?;
+            result
+        }
+        "ls" => {
+            let style = if matches.is_present("tree") {
+                ls::ListStyle::Tree
+            } else {
+                ls::ListStyle::Table
+            };
+            for_each_package(packages, |package| {
+                let dir = require_devsecrets_dir_from_manifest_dir(manifest_dir(package))?;
+                println!("{}:", package.name);
+                ls::list_files(&dir, style)?;
+                println!();
+                Ok(())
+            })
+        }
+        _ => unreachable!("Subcommand {:?} does not accept --workspace", name),
+    }
+}
+
+fn dispatch(matches: &clap::ArgMatches) -> anyhow::Result<()> {
+    let (name, sub_matches) = matches.subcommand();
+    let sub_matches = sub_matches.expect("clap requires a subcommand");
+
+    match name {
+        "projects" | "gc" | "doctor" | "completions" => {
+            run_global_command(name, sub_matches, matches)
+        }
+        _ => {
+            let workspace = workspace::CargoWorkspace::with_opt_manifest_path(
+                matches.value_of_os("manifest-path").map(Path::new),
+            )?;
+            if name == "init" && sub_matches.is_present("workspace-shared") {
+                if matches.is_present("package") {
+                    return Err(CliError::Usage(
+                        "--package cannot be used with --workspace-shared".to_string(),
+                    )
+                    .into());
+                }
+                let dir = init_workspace_shared(&workspace).context("Unable to init directory")?;
+                println!("Dir: {}", dir.display());
+                return Ok(());
+            }
+            let selection = package_selection(matches, sub_matches)?;
+            let packages = workspace.select_packages(&selection)?;
+            match selection {
+                PackageSelection::Workspace { .. } => {
+                    run_workspace_command(&packages, name, sub_matches)
+                }
+                _ => run_package_command(packages[0], name, sub_matches),
+            }
+        }
+    }
 }
 
 fn main() {
     env_logger::init();
-    let matches = cli::build_cli().get_matches();
+    let matches = match cli::build_cli().get_matches_safe() {
+        Ok(matches) => matches,
+        Err(e) => match e.kind {
+            // Help and version output are not errors.
+            clap::ErrorKind::HelpDisplayed | clap::ErrorKind::VersionDisplayed => e.exit(),
+            _ => {
+                eprintln!("{}", e.message);
+                std::process::exit(error::exit_code::USAGE);
+            }
+        },
+    };
 
     let matches = matches
         .subcommand_matches("devsecrets")
         .expect("Must have devsecrets subcommand.");
 
-    let workspace = workspace::CargoWorkspace::with_opt_manifest_path(
-        matches.value_of_os("manifest-path").map(|p| Path::new(p)),
-    )
-    .expect("");
-
-    let curr_package = match matches.value_of("package") {
-        Some(pkg_name) => workspace.find_package(pkg_name).unwrap(),
-        None => workspace.find_default_package(),
-    };
-
-    let manifest_dir = &curr_package.manifest_path.parent().unwrap();
-
-    if let Some(_) = matches.subcommand_matches("init") {
-        match init_devsecrets_dir_from_manifest_dir(manifest_dir) {
-            Ok(dir) => println!("Dir: {}", dir.to_str().unwrap()),
-            Err(e) => println!("Unable to init directory: {}", e),
-        }
-    } else if let Some(_) = matches.subcommand_matches("path") {
-        match get_devsecrets_dir_from_manifest_dir(manifest_dir) {
-            Ok(Some(dir)) => println!("{}", dir.to_str().unwrap()),
-            Ok(None) => println!("Devsecrets dir has not be initialized. Run init."),
-            Err(e) => println!("Unable to find devsecrets directory: {:#}", e),
-        }
-    } else if let Some(matches) = matches.subcommand_matches("completions") {
-        let shell = matches.value_of("SHELL").unwrap();
-        cli::build_cli().gen_completions_to(
-            "cargo",
-            shell.parse().unwrap(),
-            &mut std::io::stdout(),
-        );
+    if let Err(e) = dispatch(matches) {
+        eprintln!("error: {:#}", e);
+        std::process::exit(error::exit_code_for(&e));
     }
 }
@@ -0,0 +1,36 @@
+//! Edits the `[package.metadata.devsecrets]` table of a package manifest, preserving the rest of
+//! its formatting.
+
+use anyhow::Context;
+use devsecrets_core::DevSecretsId;
+use std::path::Path;
+use toml_edit::{DocumentMut, Item, Table};
+
+/// Returns the table at `key` within `table`, creating it if it doesn't exist.
+fn child_table<'a>(table: &'a mut Table, key: &str) -> anyhow::Result<&'a mut Table> {
+    table
+        .entry(key)
+        .or_insert_with(|| {
+            let mut child = Table::new();
+            child.set_implicit(true);
+            Item::Table(child)
+        })
+        .as_table_mut()
+        .ok_or_else(|| anyhow::anyhow!("`{}` must be a table, not an inline table or value", key))
+}
+
+/// Writes `id` to the `[package.metadata.devsecrets]` table of the manifest at `manifest_path`.
+pub fn write_manifest_id(manifest_path: &Path, id: &DevSecretsId) -> anyhow::Result<()> {
+    let contents = std::fs::read_to_string(manifest_path)?;
+    let mut manifest: DocumentMut = contents
+        .parse()
+        .with_context(|| format!("Could not parse {}", manifest_path.display()))?;
+    let package = manifest
+        .get_mut("package")
+        .and_then(Item::as_table_mut)
+        .ok_or_else(|| anyhow::anyhow!("{} has no [package] table", manifest_path.display()))?;
+    let devsecrets = child_table(child_table(package, "metadata")?, "devsecrets")?;
+    devsecrets["id"] = toml_edit::value(id.id_str());
+    std::fs::write(manifest_path, manifest.to_string())?;
+    Ok(())
+}
@@ -0,0 +1,158 @@
+//! An index of the projects that use each devsecrets directory.
+//!
+//! The index is stored as a JSON file in the devsecrets root directory, and maps each
+//! devsecrets ID to the checkouts that have been initialized with it. An ID can have multiple
+//! projects, since every clone of a repository shares the same ID.
+
+use devsecrets_core::{DevSecretsId, DevSecretsRootDir};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+const INDEX_FILE: &str = "projects.json";
+
+#[derive(Serialize, Deserialize)]
+pub struct ProjectRecord {
+    pub package: String,
+    pub manifest_path: PathBuf,
+    /// When the project was first recorded, in RFC 3339 format.
+    pub created: String,
+}
+
+impl ProjectRecord {
+    /// Returns true if the project's manifest still exists.
+    pub fn exists(&self) -> bool {
+        self.manifest_path.is_file()
+    }
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct ProjectIndex {
+    projects: BTreeMap<String, Vec<ProjectRecord>>,
+}
+
+impl ProjectIndex {
+    pub fn load(root: &DevSecretsRootDir) -> anyhow::Result<Self> {
+        let path = root.path().join(INDEX_FILE);
+        if !path.exists() {
+            return Ok(ProjectIndex::default());
+        }
+        let contents = std::fs::read(&path)?;
+        serde_json::from_slice(&contents)
+            .map_err(|e| anyhow::anyhow!("Could not parse project index {:?}: {}", path, e))
+    }
+
+    pub fn save(&self, root: &DevSecretsRootDir) -> anyhow::Result<()> {
+        // Write to a temporary file first, so that the index is never left half-written.
+        let path = root.path().join(INDEX_FILE);
+        let tmp_path = path.with_extension("json.tmp");
+        let mut file = std::fs::File::create(&tmp_path)?;
+        serde_json::to_writer_pretty(&mut file, self)?;
+        writeln!(file)?;
+        file.sync_all()?;
+        std::fs::rename(&tmp_path, &path)?;
+        Ok(())
+    }
+
+    pub fn projects(&self, id: &DevSecretsId) -> &[ProjectRecord] {
+        self.projects
+            .get(id.id_str())
+            .map(Vec::as_slice)
+            .unwrap_or(&[])
+    }
+
+    /// Records that the package with the given manifest uses the devsecrets ID `id`.
+    pub fn record(&mut self, id: &DevSecretsId, package: &str, manifest_path: &Path) {
+        let records = self.projects.entry(id.id_str().to_string()).or_default();
+        match records
+            .iter_mut()
+            .find(|record| record.manifest_path == manifest_path)
+        {
+            Some(record) => record.package = package.to_string(),
+            None => records.push(ProjectRecord {
+                package: package.to_string(),
+                manifest_path: manifest_path.to_path_buf(),
+                created: humantime::format_rfc3339_seconds(std::time::SystemTime::now())
+                    .to_string(),
+            }),
+        }
+    }
+
+    pub fn remove(&mut self, id: &DevSecretsId) {
+        self.projects.remove(id.id_str());
+    }
+}
+
+/// Records the project in the index of the given root directory.
+pub fn record_project(
+    root: &DevSecretsRootDir,
+    id: &DevSecretsId,
+    package: &str,
+    manifest_path: &Path,
+) -> anyhow::Result<()> {
+    let mut index = ProjectIndex::load(root)?;
+    index.record(id, package, manifest_path);
+    index.save(root)
+}
+
+#[derive(Serialize)]
+struct ProjectListing<'a> {
+    id: &'a str,
+    path: &'a Path,
+    projects: Vec<ProjectStatus<'a>>,
+}
+
+#[derive(Serialize)]
+struct ProjectStatus<'a> {
+    #[serde(flatten)]
+    record: &'a ProjectRecord,
+    exists: bool,
+}
+
+/// Lists every devsecrets directory in the root along with the projects that use it.
+pub fn list_projects(root: &DevSecretsRootDir, json: bool) -> anyhow::Result<()> {
+    let index = ProjectIndex::load(root)?;
+    let children = root.children()?;
+    let listings: Vec<_> = children
+        .iter()
+        .map(|(id, dir)| ProjectListing {
+            id: id.id_str(),
+            path: dir.path(),
+            projects: index
+                .projects(id)
+                .iter()
+                .map(|record| ProjectStatus {
+                    record,
+                    exists: record.exists(),
+                })
+                .collect(),
+        })
+        .collect();
+
+    let stdout = std::io::stdout();
+    let mut out = stdout.lock();
+    if json {
+        serde_json::to_writer_pretty(&mut out, &listings)?;
+        writeln!(out)?;
+        return Ok(());
+    }
+
+    for listing in &listings {
+        writeln!(out, "{}", listing.id)?;
+        if listing.projects.is_empty() {
+            writeln!(out, "    (no recorded projects)")?;
+        }
+        for status in &listing.projects {
+            writeln!(
+                out,
+                "    {} {} (created {}){}",
+                status.record.package,
+                status.record.manifest_path.display(),
+                status.record.created,
+                if status.exists { "" } else { " [missing]" }
+            )?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,36 @@
+use std::ffi::OsString;
+use std::process::Command;
+
+/// Runs `program` with `args`, adding `vars` to its environment.
+///
+/// On unix platforms the current process is replaced by the command, so its exit status and
+/// any signals it receives behave exactly as if it had been run directly. Elsewhere the
+/// command is run as a child process and this process exits with its exit code. In both
+/// cases, this function only returns if the command could not be started.
+pub fn run_with_env(
+    program: &OsString,
+    args: &[OsString],
+    vars: &[(String, String)],
+) -> anyhow::Result<()> {
+    let mut cmd = Command::new(program);
+    cmd.args(args);
+    for (name, value) in vars {
+        cmd.env(name, value);
+    }
+    let err = exec(cmd);
+    Err(anyhow::anyhow!("Could not run {:?}: {}", program, err))
+}
+
+#[cfg(unix)]
+fn exec(mut cmd: Command) -> std::io::Error {
+    use std::os::unix::process::CommandExt;
+    cmd.exec()
+}
+
+#[cfg(not(unix))]
+fn exec(mut cmd: Command) -> std::io::Error {
+    match cmd.status() {
+        Ok(status) => std::process::exit(status.code().unwrap_or(1)),
+        Err(e) => e,
+    }
+}
@@ -0,0 +1,114 @@
+use crate::dotenv;
+use cargo_metadata::Package;
+use devsecrets_core::DevSecretsDir;
+use std::path::{Path, PathBuf};
+
+/// The dotenv file within the devsecrets directory that is used when none is specified.
+pub const DEFAULT_DOTENV_FILE: &str = ".env";
+
+pub enum DotenvSource {
+    /// Use `DEFAULT_DOTENV_FILE` if it exists in the devsecrets directory.
+    Default,
+    /// Use the given relative path, which must exist.
+    File(PathBuf),
+    /// Do not read a dotenv file.
+    Disabled,
+}
+
+/// Describes how secrets map to environment variables.
+pub struct EnvMapping {
+    pub dotenv: DotenvSource,
+    /// Variables whose values are the contents of secret files. These take precedence over
+    /// values from the dotenv file, and later entries take precedence over earlier ones.
+    pub vars: Vec<(String, PathBuf)>,
+}
+
+/// Parses a `NAME=PATH` argument mapping a secret file to an environment variable.
+pub fn parse_var_arg(arg: &str) -> anyhow::Result<(String, PathBuf)> {
+    let eq = match arg.find('=') {
+        Some(eq) => eq,
+        None => anyhow::bail!("Expected NAME=PATH, got {:?}", arg),
+    };
+    let name = &arg[..eq];
+    if !dotenv::is_valid_var_name(name) {
+        anyhow::bail!("Invalid environment variable name {:?}", name);
+    }
+    Ok((name.to_string(), PathBuf::from(&arg[eq + 1..])))
+}
+
+/// Reads the variables configured in the package's `[package.metadata.devsecrets.env]` table,
+/// which maps variable names to paths of secret files.
+pub fn configured_vars(package: &Package) -> anyhow::Result<Vec<(String, PathBuf)>> {
+    let table = match package.metadata.pointer("/devsecrets/env") {
+        Some(serde_json::Value::Object(table)) => table,
+        Some(_) => anyhow::bail!("package.metadata.devsecrets.env must be a table"),
+        None => return Ok(Vec::new()),
+    };
+
+    let mut vars = Vec::new();
+    for (name, value) in table {
+        if !dotenv::is_valid_var_name(name) {
+            anyhow::bail!(
+                "Invalid environment variable name {:?} in package.metadata.devsecrets.env",
+                name
+            );
+        }
+        match value {
+            serde_json::Value::String(path) => vars.push((name.clone(), PathBuf::from(path))),
+            _ => anyhow::bail!(
+                "package.metadata.devsecrets.env.{} must be a path string",
+                name
+            ),
+        }
+    }
+    Ok(vars)
+}
+
+fn secret_file_path(dir: &DevSecretsDir, relpath: &Path) -> anyhow::Result<PathBuf> {
+    devsecrets_core::check_relative_path(relpath).map_err(anyhow::Error::msg)?;
+    Ok(dir.path().join(relpath))
+}
+
+/// Reads a secret file as a variable value, dropping a single trailing newline.
+fn read_var_value(dir: &DevSecretsDir, relpath: &Path) -> anyhow::Result<String> {
+    let path = secret_file_path(dir, relpath)?;
+    let mut value = std::fs::read_to_string(&path)
+        .map_err(|e| anyhow::anyhow!("Could not read secret file {:?}: {}", relpath, e))?;
+    if value.ends_with('\n') {
+        value.pop();
+        if value.ends_with('\r') {
+            value.pop();
+        }
+    }
+    Ok(value)
+}
+
+fn read_dotenv(dir: &DevSecretsDir, relpath: &Path) -> anyhow::Result<Vec<(String, String)>> {
+    let path = secret_file_path(dir, relpath)?;
+    let contents = std::fs::read_to_string(&path)
+        .map_err(|e| anyhow::anyhow!("Could not read dotenv file {:?}: {}", relpath, e))?;
+    dotenv::parse(&contents).map_err(|e| e.context(format!("In dotenv file {:?}", relpath)))
+}
+
+impl EnvMapping {
+    /// Resolves the mapping into environment variable names and values, in order. Later
+    /// entries override earlier entries with the same name.
+    pub fn resolve(&self, dir: &DevSecretsDir) -> anyhow::Result<Vec<(String, String)>> {
+        let mut vars = match &self.dotenv {
+            DotenvSource::Default => {
+                if dir.path().join(DEFAULT_DOTENV_FILE).is_file() {
+                    read_dotenv(dir, Path::new(DEFAULT_DOTENV_FILE))?
+                } else {
+                    Vec::new()
+                }
+            }
+            DotenvSource::File(relpath) => read_dotenv(dir, relpath)?,
+            DotenvSource::Disabled => Vec::new(),
+        };
+
+        for (name, relpath) in &self.vars {
+            vars.push((name.clone(), read_var_value(dir, relpath)?));
+        }
+        Ok(vars)
+    }
+}
@@ -1,3 +1,4 @@
+use crate::error::CliError;
 use cargo_metadata::{Metadata, Package};
 use serde::Deserialize;
 use std::path::{Path, PathBuf};
@@ -19,7 +20,7 @@ fn find_crate_root(
     if output.status.code() == Some(101) {
         anyhow::bail!(
             "Could not find manifest file: {}",
-            String::from_utf8_lossy(&output.stderr)
+            String::from_utf8_lossy(&output.stderr).trim()
         );
     }
 
@@ -40,47 +41,124 @@ fn retrieve_metadata(manifest_path: impl AsRef<Path>) -> anyhow::Result<Metadata
     Ok(cmd.exec()?)
 }
 
+/// Which packages of the workspace a command acts on.
+pub enum PackageSelection<'a> {
+    /// The package whose manifest was located.
+    Default,
+    /// The package with the given name.
+    Named(&'a str),
+    /// Every member of the workspace, except the named packages.
+    Workspace { exclude: Vec<&'a str> },
+}
+
 pub struct CargoWorkspace {
     manifest_path: PathBuf,
     metadata: Metadata,
 }
 
 impl CargoWorkspace {
-    pub fn with_opt_manifest_path(manifest_path_opt: Option<&Path>) -> anyhow::Result<Self> {
+    pub fn with_opt_manifest_path(manifest_path_opt: Option<&Path>) -> Result<Self, CliError> {
         let cargo_bin_path = match std::env::var_os("CARGO") {
             Some(p) => p,
-            None => anyhow::bail!(""),
+            None => return Err(CliError::NotRunByCargo),
         };
 
-        let working_dir = std::env::current_dir()?;
+        let load = || -> anyhow::Result<Self> {
+            let working_dir = std::env::current_dir()?;
 
-        let manifest_path = find_crate_root(cargo_bin_path, working_dir, manifest_path_opt)?;
+            let manifest_path = find_crate_root(cargo_bin_path, working_dir, manifest_path_opt)?;
 
-        let metadata = retrieve_metadata(&manifest_path)?;
+            let metadata = retrieve_metadata(&manifest_path)?;
 
-        Ok(CargoWorkspace {
-            manifest_path,
-            metadata,
-        })
+            Ok(CargoWorkspace {
+                manifest_path,
+                metadata,
+            })
+        };
+        load().map_err(CliError::Workspace)
     }
 
-    pub fn find_default_package<'a>(&'a self) -> &'a Package {
-        for package in &self.metadata.packages {
-            if package.manifest_path == self.manifest_path {
-                return package;
-            }
+    pub fn root_dir(&self) -> &Path {
+        &self.metadata.workspace_root
+    }
+
+    /// Returns the names of all packages in the workspace, sorted.
+    pub fn package_names(&self) -> Vec<String> {
+        let mut names: Vec<_> = self
+            .metadata
+            .packages
+            .iter()
+            .map(|package| package.name.clone())
+            .collect();
+        names.sort();
+        names
+    }
+
+    /// Returns the package named `name`, or the default package if no name is given.
+    pub fn select_package(&self, name: Option<&str>) -> Result<&Package, CliError> {
+        match name {
+            Some(name) => self
+                .find_package(name)
+                .ok_or_else(|| CliError::PackageNotFound {
+                    name: name.to_string(),
+                    available: self.package_names(),
+                }),
+            None => self
+                .find_default_package()
+                .ok_or_else(|| CliError::NoDefaultPackage {
+                    manifest_path: self.manifest_path.clone(),
+                    available: self.package_names(),
+                }),
         }
+    }
+
+    pub fn find_default_package(&self) -> Option<&Package> {
+        self.metadata
+            .packages
+            .iter()
+            .find(|package| package.manifest_path == self.manifest_path)
+    }
 
-        panic!("Metadata must include the default package");
+    pub fn find_package(&self, name: &str) -> Option<&Package> {
+        self.metadata
+            .packages
+            .iter()
+            .find(|package| package.name == name)
     }
 
-    pub fn find_package<'a>(&'a self, name: &str) -> Option<&'a Package> {
-        for package in &self.metadata.packages {
-            if package.name == name {
-                return Some(package);
+    /// Returns the workspace members, sorted by name.
+    pub fn members(&self) -> Vec<&Package> {
+        let mut members: Vec<_> = self
+            .metadata
+            .packages
+            .iter()
+            .filter(|package| self.metadata.workspace_members.contains(&package.id))
+            .collect();
+        members.sort_by(|a, b| a.name.cmp(&b.name));
+        members
+    }
+
+    /// Returns the packages chosen by `selection`.
+    pub fn select_packages(&self, selection: &PackageSelection) -> Result<Vec<&Package>, CliError> {
+        match selection {
+            PackageSelection::Default => Ok(vec![self.select_package(None)?]),
+            PackageSelection::Named(name) => Ok(vec![self.select_package(Some(name))?]),
+            PackageSelection::Workspace { exclude } => {
+                if let Some(name) = exclude
+                    .iter()
+                    .find(|name| self.find_package(name).is_none())
+                {
+                    return Err(CliError::PackageNotFound {
+                        name: name.to_string(),
+                        available: self.package_names(),
+                    });
+                }
+                Ok(self
+                    .members()
+                    .into_iter()
+                    .filter(|package| !exclude.contains(&package.name.as_str()))
+                    .collect())
             }
         }
-
-        None
     }
 }
@@ -1,31 +1,362 @@
 use std::borrow::Cow;
 use std::io;
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
 use uuid::Uuid;
 
 pub const DEVSECRETS_CONFIG_DIR: &str = "rust-devsecrets";
 pub const DEVSECRETS_ID_FILE: &str = ".devsecrets_id.txt";
 
-fn read_uuid(manifest_dir: impl AsRef<Path>) -> io::Result<Option<Uuid>> {
-    let uuid_file = manifest_dir.as_ref().join(DEVSECRETS_ID_FILE);
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Reads a file to a string, adding the path to any error.
+fn read_to_string(path: &Path) -> io::Result<String> {
+    std::fs::read_to_string(path)
+        .map_err(|e| io::Error::new(e.kind(), format!("Could not read {:?}: {}", path, e)))
+}
+
+/// Reads a UUID from an ID file. Whitespace around the UUID, such as a trailing newline added by
+/// an editor, is ignored.
+fn read_uuid_file(uuid_file: &Path) -> io::Result<Option<Uuid>> {
     if !uuid_file.exists() {
         return Ok(None);
     }
-    let contents = std::fs::read_to_string(&uuid_file)?;
-    let uuid = match Uuid::parse_str(&contents) {
+    let contents = read_to_string(uuid_file)?;
+    let uuid = match Uuid::parse_str(contents.trim()) {
         Ok(uuid) => uuid,
         Err(_) => {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                format!("Did not read valid UUID from {:?}", uuid_file),
-            ))
+            return Err(invalid_data(format!(
+                "{:?} does not contain a valid UUID",
+                uuid_file
+            )))
         }
     };
     Ok(Some(uuid))
 }
 
+/// The error wrapped by the `io::Error` returned when a `Cargo.toml` file is not valid TOML, so
+/// that it can be told apart from a malformed ID.
+#[derive(Debug)]
+pub struct ManifestParseError {
+    pub manifest_path: PathBuf,
+    message: String,
+}
+
+impl ManifestParseError {
+    /// Returns the `ManifestParseError` wrapped by `error`, if any.
+    pub fn find(error: &io::Error) -> Option<&ManifestParseError> {
+        error.get_ref()?.downcast_ref()
+    }
+}
+
+impl std::fmt::Display for ManifestParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "Could not parse {:?}: {}",
+            self.manifest_path, self.message
+        )
+    }
+}
+
+impl std::error::Error for ManifestParseError {}
+
+fn read_manifest(manifest_path: &Path) -> io::Result<toml::Value> {
+    let contents = read_to_string(manifest_path)?;
+    contents.parse().map_err(|e: toml::de::Error| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            ManifestParseError {
+                manifest_path: manifest_path.to_path_buf(),
+                message: e.to_string(),
+            },
+        )
+    })
+}
+
+/// Reads the `id` key of the `devsecrets` table within `metadata`, if it exists.
+fn read_metadata_id(
+    metadata: Option<&toml::Value>,
+    manifest_path: &Path,
+) -> io::Result<Option<Uuid>> {
+    let id = match metadata
+        .and_then(|metadata| metadata.get("devsecrets"))
+        .and_then(|devsecrets| devsecrets.get("id"))
+    {
+        Some(id) => id,
+        None => return Ok(None),
+    };
+    match id.as_str().map(Uuid::parse_str) {
+        Some(Ok(uuid)) => Ok(Some(uuid)),
+        _ => Err(invalid_data(format!(
+            "The devsecrets id in {:?} is not a valid UUID",
+            manifest_path
+        ))),
+    }
+}
+
+/// Returns the strings in the array at `key` of the `[workspace]` table, ignoring other values.
+fn workspace_paths<'a>(workspace: &'a toml::Value, key: &str) -> Vec<&'a str> {
+    workspace
+        .get(key)
+        .and_then(toml::Value::as_array)
+        .map(|paths| paths.iter().filter_map(toml::Value::as_str).collect())
+        .unwrap_or_default()
+}
+
+/// Matches a single path component against a pattern component, where `*` matches any sequence of
+/// characters and `?` matches any single character.
+fn component_matches(pattern: &[char], name: &[char]) -> bool {
+    match (pattern.first(), name.first()) {
+        (None, None) => true,
+        (Some('*'), _) => {
+            component_matches(&pattern[1..], name)
+                || (!name.is_empty() && component_matches(pattern, &name[1..]))
+        }
+        (Some('?'), Some(_)) => component_matches(&pattern[1..], &name[1..]),
+        (Some(p), Some(n)) if p == n => component_matches(&pattern[1..], &name[1..]),
+        _ => false,
+    }
+}
+
+/// Matches a relative path against a `members` glob, where `**` matches any number of
+/// directories.
+fn glob_matches(pattern: &[String], path: &[String]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((first, rest)) if first == "**" => {
+            glob_matches(rest, path) || (!path.is_empty() && glob_matches(pattern, &path[1..]))
+        }
+        Some((first, rest)) => match path.split_first() {
+            Some((name, path_rest)) => {
+                let first: Vec<char> = first.chars().collect();
+                let name: Vec<char> = name.chars().collect();
+                component_matches(&first, &name) && glob_matches(rest, path_rest)
+            }
+            None => false,
+        },
+    }
+}
+
+/// Splits a relative path into its normal components.
+fn path_components(path: &Path) -> Vec<String> {
+    path.components()
+        .filter_map(|component| match component {
+            std::path::Component::Normal(name) => Some(name.to_string_lossy().into_owned()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Returns true if the workspace in `workspace_dir` includes the package in `package_dir`,
+/// following cargo's rules: the package must match one of the `members`, and must not be under
+/// one of the `exclude` paths unless it is listed in `members` explicitly.
+///
+/// Path dependencies of the workspace's root package are also members in cargo, but are not
+/// recognized here.
+fn workspace_includes(workspace_dir: &Path, workspace: &toml::Value, package_dir: &Path) -> bool {
+    let relpath = match package_dir.strip_prefix(workspace_dir) {
+        Ok(relpath) => path_components(relpath),
+        Err(_) => return false,
+    };
+    let members = workspace_paths(workspace, "members");
+    let explicit_member = members
+        .iter()
+        .any(|member| path_components(Path::new(member)) == relpath);
+    if explicit_member {
+        return true;
+    }
+    let excluded = workspace_paths(workspace, "exclude")
+        .iter()
+        .any(|exclude| relpath.starts_with(&path_components(Path::new(exclude))));
+    !excluded
+        && members
+            .iter()
+            .any(|member| glob_matches(&path_components(Path::new(member)), &relpath))
+}
+
+/// Finds the manifest of the workspace containing the package in `manifest_dir`.
+///
+/// This is the package's own manifest if it has a `[workspace]` table, the manifest named by its
+/// `package.workspace` key, or else the manifest of the nearest parent directory whose
+/// `[workspace]` table includes the package as a member. Parent manifests that can't be read or
+/// parsed are skipped, since they may belong to unrelated projects.
+pub fn find_workspace_manifest(manifest_dir: impl AsRef<Path>) -> io::Result<Option<PathBuf>> {
+    let manifest_dir = manifest_dir.as_ref();
+    let manifest_path = manifest_dir.join("Cargo.toml");
+    if manifest_path.exists() {
+        let manifest = read_manifest(&manifest_path)?;
+        if manifest.get("workspace").is_some() {
+            return Ok(Some(manifest_path));
+        }
+        let workspace_dir = manifest
+            .get("package")
+            .and_then(|package| package.get("workspace"))
+            .and_then(toml::Value::as_str);
+        if let Some(workspace_dir) = workspace_dir {
+            return Ok(Some(manifest_dir.join(workspace_dir).join("Cargo.toml")));
+        }
+    }
+
+    for dir in manifest_dir.ancestors().skip(1) {
+        let candidate = dir.join("Cargo.toml");
+        if !candidate.exists() {
+            continue;
+        }
+        let manifest = match read_manifest(&candidate) {
+            Ok(manifest) => manifest,
+            Err(_) => continue,
+        };
+        if let Some(workspace) = manifest.get("workspace") {
+            if workspace_includes(dir, workspace, manifest_dir) {
+                return Ok(Some(candidate));
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Reads the secret paths declared by the `secrets` key of the `[package.metadata.devsecrets]`
+/// table of the package manifest, or `None` if the package does not declare them.
+pub fn read_declared_secrets(manifest_dir: impl AsRef<Path>) -> io::Result<Option<Vec<String>>> {
+    let manifest_path = manifest_dir.as_ref().join("Cargo.toml");
+    let manifest = read_manifest(&manifest_path)?;
+    let secrets = match manifest
+        .get("package")
+        .and_then(|package| package.get("metadata")?.get("devsecrets")?.get("secrets"))
+    {
+        Some(secrets) => secrets,
+        None => return Ok(None),
+    };
+    let invalid = || {
+        invalid_data(format!(
+            "The devsecrets secrets in {:?} must be an array of strings",
+            manifest_path
+        ))
+    };
+    secrets
+        .as_array()
+        .ok_or_else(invalid)?
+        .iter()
+        .map(|secret| secret.as_str().map(str::to_string).ok_or_else(invalid))
+        .collect::<io::Result<_>>()
+        .map(Some)
+}
+
+/// Where a devsecrets ID was read from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum IdSource {
+    /// The ID file in the package directory.
+    PackageFile(PathBuf),
+    /// The `[package.metadata.devsecrets]` table of the package manifest.
+    PackageManifest(PathBuf),
+    /// The ID file next to the workspace manifest, shared by all members of the workspace.
+    WorkspaceFile(PathBuf),
+    /// The `[workspace.metadata.devsecrets]` table of the workspace manifest.
+    WorkspaceManifest(PathBuf),
+}
+
+impl IdSource {
+    /// Returns the path of the file the ID was read from.
+    pub fn path(&self) -> &Path {
+        match self {
+            IdSource::PackageFile(path)
+            | IdSource::PackageManifest(path)
+            | IdSource::WorkspaceFile(path)
+            | IdSource::WorkspaceManifest(path) => path,
+        }
+    }
+
+    /// Returns true if the ID is shared by the members of a workspace.
+    pub fn is_shared(&self) -> bool {
+        matches!(
+            self,
+            IdSource::WorkspaceFile(_) | IdSource::WorkspaceManifest(_)
+        )
+    }
+}
+
+/// A devsecrets ID, along with where it was read from.
+pub struct LocatedId {
+    pub id: DevSecretsId,
+    pub source: IdSource,
+}
+
+/// Finds the devsecrets ID for the package in `manifest_dir`.
+///
+/// The ID file in the package directory takes precedence, followed by the
+/// `[package.metadata.devsecrets]` table of the package manifest. Otherwise, the ID is shared with
+/// the rest of the workspace, and is read from an ID file in the workspace root directory, or from
+/// the `[workspace.metadata.devsecrets]` table of the workspace manifest.
+pub fn locate_devsecrets_id(manifest_dir: impl AsRef<Path>) -> io::Result<Option<LocatedId>> {
+    let manifest_dir = manifest_dir.as_ref();
+    let located = |uuid, source| LocatedId {
+        id: DevSecretsId::from_uuid(uuid),
+        source,
+    };
+
+    let package_file = manifest_dir.join(DEVSECRETS_ID_FILE);
+    if let Some(uuid) = read_uuid_file(&package_file)? {
+        return Ok(Some(located(uuid, IdSource::PackageFile(package_file))));
+    }
+
+    let package_manifest = manifest_dir.join("Cargo.toml");
+    if package_manifest.exists() {
+        let manifest = read_manifest(&package_manifest)?;
+        let metadata = manifest
+            .get("package")
+            .and_then(|package| package.get("metadata"));
+        if let Some(uuid) = read_metadata_id(metadata, &package_manifest)? {
+            return Ok(Some(located(
+                uuid,
+                IdSource::PackageManifest(package_manifest),
+            )));
+        }
+    }
+
+    let workspace_manifest = match find_workspace_manifest(manifest_dir)? {
+        Some(workspace_manifest) => workspace_manifest,
+        None => return Ok(None),
+    };
+    let workspace_dir = workspace_manifest
+        .parent()
+        .expect("Manifest path must have a parent");
+    let workspace_file = workspace_dir.join(DEVSECRETS_ID_FILE);
+    if let Some(uuid) = read_uuid_file(&workspace_file)? {
+        return Ok(Some(located(uuid, IdSource::WorkspaceFile(workspace_file))));
+    }
+
+    let manifest = read_manifest(&workspace_manifest)?;
+    let metadata = manifest
+        .get("workspace")
+        .and_then(|workspace| workspace.get("metadata"));
+    Ok(read_metadata_id(metadata, &workspace_manifest)?
+        .map(|uuid| located(uuid, IdSource::WorkspaceManifest(workspace_manifest))))
+}
+
+/// Checks that `relpath` can be used to refer to a file within a devsecrets directory.
+///
+/// The path must be relative and consist only of normal components. On failure, returns a
+/// message describing the problem.
+pub fn check_relative_path(relpath: &Path) -> Result<(), String> {
+    if relpath.is_absolute() {
+        return Err(format!("Path {:?} must not be absolute.", relpath));
+    }
+
+    // Check that we only have normal parts of the path
+    for component in relpath.components() {
+        match component {
+            Component::Normal(_) => (),
+            _ => return Err(format!("Path {:?} has a non-normal component.", relpath)),
+        }
+    }
+
+    Ok(())
+}
+
 pub fn read_devsecrets_id(manifest_dir: impl AsRef<Path>) -> io::Result<Option<DevSecretsId>> {
-    Ok(read_uuid(manifest_dir)?.map(DevSecretsId::from_uuid))
+    Ok(locate_devsecrets_id(manifest_dir)?.map(|located| located.id))
 }
 
 pub fn ensure_devsecrets_id(manifest_dir: impl AsRef<Path>) -> io::Result<DevSecretsId> {
@@ -85,6 +416,30 @@ impl DevSecretsRootDir {
         DevSecretsRootDir::ensure_with_config_root(config_root)
     }
 
+    pub fn path(&self) -> &Path {
+        &self.config_dir
+    }
+
+    /// Returns the devsecrets directories in this root, sorted by ID.
+    ///
+    /// Entries whose names are not devsecrets IDs are ignored.
+    pub fn children(&self) -> io::Result<Vec<(DevSecretsId, DevSecretsDir)>> {
+        let mut children = Vec::new();
+        for entry in std::fs::read_dir(&self.config_dir)? {
+            let entry = entry?;
+            if !entry.file_type()?.is_dir() {
+                continue;
+            }
+            let id = match entry.file_name().to_str().and_then(DevSecretsId::parse) {
+                Some(id) => id,
+                None => continue,
+            };
+            children.push((id, DevSecretsDir { dir: entry.path() }));
+        }
+        children.sort_by(|(a, _), (b, _)| a.id_str().cmp(b.id_str()));
+        Ok(children)
+    }
+
     pub fn get_child(&self, id: &DevSecretsId) -> io::Result<Option<DevSecretsDir>> {
         let child_dir = self.config_dir.join(id.id_str());
 
@@ -103,11 +458,44 @@ impl DevSecretsRootDir {
     pub fn ensure_child(&self, id: &DevSecretsId) -> io::Result<DevSecretsDir> {
         let child_dir = self.config_dir.join(id.id_str());
 
-        std::fs::create_dir_all(&child_dir)?;
+        create_private_dir_all(&child_dir)?;
         Ok(DevSecretsDir { dir: child_dir })
     }
 }
 
+/// Creates a directory and its parents. On unix, newly created directories are only accessible
+/// by the current user.
+fn create_private_dir_all(path: &Path) -> io::Result<()> {
+    let mut builder = std::fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(0o700);
+    }
+    builder.create(path)
+}
+
+/// Creates or truncates a file for writing secret data. On unix, the file is only accessible by
+/// the current user, including when it already existed with other permissions.
+pub fn create_private_file(path: &Path) -> io::Result<std::fs::File> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let file = options.open(path)?;
+    // The mode only applies to newly created files.
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(std::fs::Permissions::from_mode(0o600))?;
+    }
+    Ok(file)
+}
+
 pub struct DevSecretsId(pub Cow<'static, str>);
 
 impl DevSecretsId {
@@ -121,6 +509,11 @@ impl DevSecretsId {
         )
     }
 
+    /// Parses an ID from its string form, returning `None` if it is not a valid UUID.
+    pub fn parse(id: &str) -> Option<Self> {
+        Uuid::parse_str(id).ok().map(DevSecretsId::from_uuid)
+    }
+
     pub fn from_uuid(uuid: Uuid) -> Self {
         let mut buffer = Uuid::encode_buffer();
         let uuid_str = uuid.to_hyphenated().encode_lower(&mut buffer);
@@ -137,7 +530,104 @@ pub struct DevSecretsDir {
 }
 
 impl DevSecretsDir {
-    pub fn path<'a>(&'a self) -> &'a Path {
+    /// Uses an arbitrary directory as a devsecrets directory, such as a directory of test
+    /// fixtures.
+    pub fn from_path(dir: impl Into<PathBuf>) -> Self {
+        DevSecretsDir { dir: dir.into() }
+    }
+
+    pub fn path(&self) -> &Path {
         &self.dir
     }
+
+    /// Returns the paths of all files in this directory, relative to it and in sorted order.
+    pub fn files(&self) -> io::Result<Vec<PathBuf>> {
+        let mut files = Vec::new();
+        collect_files(&self.dir, Path::new(""), &mut files)?;
+        files.sort();
+        Ok(files)
+    }
+}
+
+fn collect_files(root: &Path, relpath: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in std::fs::read_dir(root.join(relpath))? {
+        let entry = entry?;
+        let entry_relpath = relpath.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            collect_files(root, &entry_relpath, files)?;
+        } else {
+            files.push(entry_relpath);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn includes(workspace: &str, package_relpath: &str) -> bool {
+        let manifest: toml::Value = workspace.parse().unwrap();
+        let root = Path::new("/ws");
+        workspace_includes(
+            root,
+            manifest.get("workspace").unwrap(),
+            &root.join(package_relpath),
+        )
+    }
+
+    #[test]
+    fn workspace_members_follow_cargo_rules() {
+        let workspace = r#"
+            [workspace]
+            members = ["app", "crates/*", "tools/**/cli", "vendor/kept"]
+            exclude = ["crates/old", "vendor"]
+        "#;
+        assert!(includes(workspace, "app"));
+        assert!(includes(workspace, "crates/core"));
+        assert!(includes(workspace, "tools/cli"));
+        assert!(includes(workspace, "tools/a/b/cli"));
+        // Explicit members are included even when under an excluded path.
+        assert!(includes(workspace, "vendor/kept"));
+
+        assert!(!includes(workspace, "crates/old"));
+        assert!(!includes(workspace, "crates/core/nested"));
+        assert!(!includes(workspace, "vendor/other"));
+        assert!(!includes(workspace, "unrelated"));
+        assert!(!includes("[workspace]", "app"));
+    }
+
+    #[test]
+    fn manifest_parse_errors_are_distinct_from_malformed_ids() {
+        let dir = std::env::temp_dir().join(format!(
+            "devsecrets-core-test-{}",
+            DevSecretsId::new_unique().id_str()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("Cargo.toml"), "[package\n").unwrap();
+        let manifest_error = locate_devsecrets_id(&dir).err().unwrap();
+        std::fs::write(dir.join(DEVSECRETS_ID_FILE), "not-a-uuid").unwrap();
+        let id_error = locate_devsecrets_id(&dir).err().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let parse_error = ManifestParseError::find(&manifest_error).unwrap();
+        assert_eq!(parse_error.manifest_path, dir.join("Cargo.toml"));
+        assert_eq!(id_error.kind(), io::ErrorKind::InvalidData);
+        assert!(ManifestParseError::find(&id_error).is_none());
+    }
+
+    #[test]
+    fn glob_components() {
+        let matches = |pattern: &str, name: &str| {
+            component_matches(
+                &pattern.chars().collect::<Vec<_>>(),
+                &name.chars().collect::<Vec<_>>(),
+            )
+        };
+        assert!(matches("*", "anything"));
+        assert!(matches("devsecrets-*", "devsecrets-core"));
+        assert!(matches("a?c", "abc"));
+        assert!(!matches("a?c", "ac"));
+        assert!(!matches("devsecrets-*", "cargo-devsecrets"));
+    }
 }
@@ -0,0 +1,250 @@
+//! `#[derive(DevSecrets)]`.
+
+use proc_macro2::{Span, TokenStream};
+use quote::{format_ident, quote};
+use std::path::Path;
+
+/// How a secret file is read into its field.
+enum SecretFormat {
+    Json,
+    Toml,
+    Text,
+    Bytes,
+}
+
+impl SecretFormat {
+    fn from_name(name: &syn::LitStr) -> syn::Result<Self> {
+        match name.value().as_str() {
+            "json" => Ok(SecretFormat::Json),
+            "toml" => Ok(SecretFormat::Toml),
+            "text" => Ok(SecretFormat::Text),
+            "bytes" => Ok(SecretFormat::Bytes),
+            _ => Err(syn::Error::new(
+                name.span(),
+                "Unknown format. Expected one of \"json\", \"toml\", \"text\" or \"bytes\".",
+            )),
+        }
+    }
+
+    /// Infers the format from a path's extension, defaulting to text.
+    fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => SecretFormat::Json,
+            Some("toml") => SecretFormat::Toml,
+            _ => SecretFormat::Text,
+        }
+    }
+
+    /// The extension a path must have to be read in this format, if any.
+    fn required_extension(&self) -> Option<&'static str> {
+        match self {
+            SecretFormat::Json => Some("json"),
+            SecretFormat::Toml => Some("toml"),
+            SecretFormat::Text | SecretFormat::Bytes => None,
+        }
+    }
+}
+
+struct SecretField {
+    ident: syn::Ident,
+    /// The type read from the file. For `Option<T>` fields, this is `T`.
+    ty: syn::Type,
+    optional: bool,
+    path: syn::LitStr,
+    format: SecretFormat,
+}
+
+/// Returns `T` if `ty` is `Option<T>`.
+fn option_inner(ty: &syn::Type) -> Option<&syn::Type> {
+    let path = match ty {
+        syn::Type::Path(ty) if ty.qself.is_none() => &ty.path,
+        _ => return None,
+    };
+    let segment = path.segments.last()?;
+    if segment.ident != "Option" {
+        return None;
+    }
+    match &segment.arguments {
+        syn::PathArguments::AngleBracketed(args) if args.args.len() == 1 => match &args.args[0] {
+            syn::GenericArgument::Type(ty) => Some(ty),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn parse_field(field: &syn::Field) -> syn::Result<SecretField> {
+    let ident = field.ident.clone().expect("Named fields have identifiers");
+    let mut attrs = field
+        .attrs
+        .iter()
+        .filter(|attr| attr.path.is_ident("secret"));
+    let attr = attrs.next().ok_or_else(|| {
+        syn::Error::new_spanned(
+            &ident,
+            "Every field must have a #[secret(path = \"...\")] attribute",
+        )
+    })?;
+    if let Some(extra) = attrs.next() {
+        return Err(syn::Error::new_spanned(
+            extra,
+            "Only one #[secret] attribute is allowed per field",
+        ));
+    }
+
+    let list = match attr.parse_meta()? {
+        syn::Meta::List(list) => list,
+        meta => {
+            return Err(syn::Error::new_spanned(
+                meta,
+                "Expected #[secret(path = \"...\")]",
+            ))
+        }
+    };
+    let mut path = None;
+    let mut format = None;
+    for nested in &list.nested {
+        let (name, value) = match nested {
+            syn::NestedMeta::Meta(syn::Meta::NameValue(syn::MetaNameValue {
+                path,
+                lit: syn::Lit::Str(value),
+                ..
+            })) => (path, value),
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    nested,
+                    "Expected `path = \"...\"` or `format = \"...\"`",
+                ))
+            }
+        };
+        if name.is_ident("path") {
+            path = Some(value.clone());
+        } else if name.is_ident("format") {
+            format = Some(SecretFormat::from_name(value)?);
+        } else {
+            return Err(syn::Error::new_spanned(
+                name,
+                "Unknown key. Expected `path` or `format`.",
+            ));
+        }
+    }
+
+    let path = path.ok_or_else(|| syn::Error::new_spanned(&list, "Missing `path = \"...\"`"))?;
+    let relpath = path.value();
+    let relpath = Path::new(&relpath);
+    devsecrets_core::check_relative_path(relpath).map_err(|e| syn::Error::new(path.span(), e))?;
+    let format = format.unwrap_or_else(|| SecretFormat::from_path(relpath));
+    if let Some(ext) = format.required_extension() {
+        if relpath.extension() != Some(ext.as_ref()) {
+            return Err(syn::Error::new(
+                path.span(),
+                format!("Path must have a .{} extension to be read as {}", ext, ext),
+            ));
+        }
+    }
+
+    let (ty, optional) = match option_inner(&field.ty) {
+        Some(inner) => (inner.clone(), true),
+        None => (field.ty.clone(), false),
+    };
+    Ok(SecretField {
+        ident,
+        ty,
+        optional,
+        path,
+        format,
+    })
+}
+
+/// Generates the statement that reads `field` into a local `Option`, which is `None` if it
+/// failed and the error was recorded. Locals are prefixed with `__devsecrets_` so they can't
+/// clash with names from the user's code.
+fn load_field(field: &SecretField, local: &syn::Ident) -> TokenStream {
+    let SecretField { ty, path, .. } = field;
+    let read = match field.format {
+        SecretFormat::Json => quote! {
+            __devsecrets_secrets.read_from(#path).with_format(::devsecrets::JsonFormat).into_value::<#ty>()
+        },
+        SecretFormat::Toml => quote! {
+            __devsecrets_secrets.read_from(#path).with_format(::devsecrets::TomlFormat).into_value::<#ty>()
+        },
+        SecretFormat::Text => quote!(__devsecrets_secrets.read_from(#path).to_string()),
+        SecretFormat::Bytes => quote!(__devsecrets_secrets.read_from(#path).to_bytes()),
+    };
+    let (value, missing) = if field.optional {
+        (
+            quote!(::std::option::Option::Some(__devsecrets_value)),
+            quote! {
+                ::std::result::Result::Err(__devsecrets_error) if __devsecrets_error.is_not_found() => {
+                    ::std::option::Option::Some(::std::option::Option::None)
+                }
+            },
+        )
+    } else {
+        (quote!(__devsecrets_value), quote!())
+    };
+    quote! {
+        let #local = match #read {
+            ::std::result::Result::Ok(__devsecrets_value) => ::std::option::Option::Some(#value),
+            #missing
+            ::std::result::Result::Err(__devsecrets_error) => {
+                __devsecrets_errors.push((::std::path::PathBuf::from(#path), __devsecrets_error));
+                ::std::option::Option::None
+            }
+        };
+    }
+}
+
+pub fn derive_impl(input: TokenStream) -> syn::Result<TokenStream> {
+    let input: syn::DeriveInput = syn::parse2(input)?;
+    let fields = match &input.data {
+        syn::Data::Struct(syn::DataStruct {
+            fields: syn::Fields::Named(fields),
+            ..
+        }) => &fields.named,
+        _ => {
+            return Err(syn::Error::new(
+                Span::call_site(),
+                "#[derive(DevSecrets)] only supports structs with named fields",
+            ))
+        }
+    };
+    let fields = fields
+        .iter()
+        .map(parse_field)
+        .collect::<syn::Result<Vec<_>>>()?;
+
+    let locals: Vec<_> = fields
+        .iter()
+        .map(|field| format_ident!("__devsecrets_field_{}", field.ident))
+        .collect();
+    let loads = fields
+        .iter()
+        .zip(&locals)
+        .map(|(field, local)| load_field(field, local));
+    let idents = fields.iter().map(|field| &field.ident);
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics #name #ty_generics #where_clause {
+            /// Loads every field from its secret file, reporting all fields that could not be
+            /// loaded.
+            pub fn load(
+                __devsecrets_secrets: &::devsecrets::DevSecrets,
+            ) -> ::std::result::Result<Self, ::devsecrets::LoadError> {
+                let mut __devsecrets_errors: ::std::vec::Vec<(::std::path::PathBuf, ::devsecrets::Error)> =
+                    ::std::vec::Vec::new();
+                #(#loads)*
+                if !__devsecrets_errors.is_empty() {
+                    return ::std::result::Result::Err(::devsecrets::LoadError::from_errors(
+                        __devsecrets_errors,
+                    ));
+                }
+                ::std::result::Result::Ok(Self {
+                    #(#idents: #locals.expect("Fields without errors were loaded"),)*
+                })
+            }
+        }
+    })
+}
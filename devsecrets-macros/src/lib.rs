@@ -1,26 +1,80 @@
 extern crate proc_macro;
 use proc_macro::TokenStream;
 
+mod derive;
+mod secret_path;
+mod test_attr;
+
 mod macro_impls {
     use proc_macro2::{Span, TokenStream};
     use quote::quote;
-    use std::path::PathBuf;
+    use std::path::{Path, PathBuf};
+
+    /// The kind of item declared by `devsecrets_id!`.
+    enum ItemKind {
+        Static,
+        Const,
+    }
 
-    struct DevsecretsIdDecl;
+    /// A declaration of a named ID, such as `pub SECRET_ID` or `const SECRET_ID`.
+    struct NamedIdDecl {
+        attrs: Vec<syn::Attribute>,
+        vis: syn::Visibility,
+        kind: ItemKind,
+        ident: syn::Ident,
+    }
+
+    /// The input to `devsecrets_id!`. With no arguments, the macro expands to an expression.
+    struct DevsecretsIdDecl(Option<NamedIdDecl>);
 
     impl syn::parse::Parse for DevsecretsIdDecl {
         fn parse(stream: syn::parse::ParseStream) -> syn::parse::Result<Self> {
-            if !stream.is_empty() {
-                Err(stream.error("devsecrets_id!() must have no arguments"))
+            if stream.is_empty() {
+                return Ok(DevsecretsIdDecl(None));
+            }
+
+            let attrs = stream.call(syn::Attribute::parse_outer)?;
+            let vis = stream.parse()?;
+            let kind = if stream.peek(syn::Token![const]) {
+                stream.parse::<syn::Token![const]>()?;
+                ItemKind::Const
             } else {
-                Ok(DevsecretsIdDecl)
+                if stream.peek(syn::Token![static]) {
+                    stream.parse::<syn::Token![static]>()?;
+                }
+                ItemKind::Static
+            };
+            let ident = stream.parse()?;
+            if stream.peek(syn::Token![;]) {
+                stream.parse::<syn::Token![;]>()?;
             }
+            if !stream.is_empty() {
+                return Err(stream.error("Unexpected tokens after the ID name"));
+            }
+            Ok(DevsecretsIdDecl(Some(NamedIdDecl {
+                attrs,
+                vis,
+                kind,
+                ident,
+            })))
         }
     }
 
-    pub fn devsecrets_id_impl(input: TokenStream) -> syn::Result<TokenStream> {
-        let _ = syn::parse2::<DevsecretsIdDecl>(input)?;
+    fn missing_id_error(manifest_dir: &Path) -> syn::Error {
+        syn::Error::new(
+            Span::call_site(),
+            format!(
+                "No devsecrets ID found for this crate. Looked for {:?}, an `id` in the \
+                 [package.metadata.devsecrets] table of {:?}, and an ID shared by the workspace. \
+                 Run `cargo devsecrets init` to create one.",
+                manifest_dir.join(devsecrets_core::DEVSECRETS_ID_FILE),
+                manifest_dir.join("Cargo.toml"),
+            ),
+        )
+    }
 
+    /// Returns an expression evaluating to the `devsecrets::Id` of the crate being compiled.
+    pub fn id_expr() -> syn::Result<TokenStream> {
         let manifest_dir: PathBuf = std::env::var_os("CARGO_MANIFEST_DIR")
             .ok_or_else(|| {
                 syn::Error::new(
@@ -29,20 +83,97 @@ mod macro_impls {
                 )
             })?
             .into();
-        let id = devsecrets_core::read_devsecrets_id(manifest_dir)
-            .map_err(|e| {
-                syn::Error::new(
+        let located = match devsecrets_core::locate_devsecrets_id(&manifest_dir) {
+            Ok(Some(located)) => located,
+            Ok(None) => return Err(missing_id_error(&manifest_dir)),
+            Err(e) if devsecrets_core::ManifestParseError::find(&e).is_some() => {
+                return Err(syn::Error::new(
                     Span::call_site(),
-                    format!("Problem reading uuid file: {}", e),
-                )
-            })?
-            .ok_or_else(|| syn::Error::new(Span::call_site(), "Uuid file does not exist"))?;
+                    format!(
+                        "Unable to read the devsecrets ID: {}. Fix the syntax error in the \
+                         manifest.",
+                        e
+                    ),
+                ))
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::InvalidData => {
+                return Err(syn::Error::new(
+                    Span::call_site(),
+                    format!(
+                        "Malformed devsecrets ID: {}. Restore it from version control, or \
+                         remove it and run `cargo devsecrets init` to create a new ID.",
+                        e
+                    ),
+                ))
+            }
+            Err(e) => {
+                return Err(syn::Error::new(
+                    Span::call_site(),
+                    format!(
+                        "Unable to read the devsecrets ID: {}. Check that the file exists and is \
+                         readable.",
+                        e
+                    ),
+                ))
+            }
+        };
 
-        let uuid_str = syn::LitStr::new(id.id_str(), Span::call_site());
+        let uuid_str = syn::LitStr::new(located.id.id_str(), Span::call_site());
+
+        // Including the files makes cargo rebuild the crate when the ID changes. Both manifests
+        // are tracked whichever source won, since an ID added to either can take precedence or
+        // replace the current one. Files that don't exist yet can't be included, so creating a
+        // new ID file is not noticed. Paths that are not valid UTF-8 can't be passed to
+        // include_bytes!, so they are not tracked either.
+        let mut tracked = vec![located.source.path().to_path_buf()];
+        let package_manifest = manifest_dir.join("Cargo.toml");
+        if package_manifest.exists() {
+            tracked.push(package_manifest);
+        }
+        if let Ok(Some(workspace_manifest)) =
+            devsecrets_core::find_workspace_manifest(&manifest_dir)
+        {
+            tracked.push(workspace_manifest);
+        }
+        tracked.sort();
+        tracked.dedup();
+        let track_sources = tracked.iter().filter_map(|path| path.to_str()).map(|path| {
+            let path = syn::LitStr::new(path, Span::call_site());
+            quote!(
+                const _: &[u8] = ::std::include_bytes!(#path);
+            )
+        });
 
         Ok(quote! {
+            {
+                #(#track_sources)*
                 ::devsecrets::Id(::devsecrets::internal_core::DevSecretsId(
                     ::std::borrow::Cow::Borrowed(#uuid_str)))
+            }
+        })
+    }
+
+    pub fn devsecrets_id_impl(input: TokenStream) -> syn::Result<TokenStream> {
+        let DevsecretsIdDecl(decl) = syn::parse2::<DevsecretsIdDecl>(input)?;
+        let id_expr = id_expr()?;
+
+        Ok(match decl {
+            None => id_expr,
+            Some(NamedIdDecl {
+                attrs,
+                vis,
+                kind,
+                ident,
+            }) => {
+                let kind = match kind {
+                    ItemKind::Static => quote!(static),
+                    ItemKind::Const => quote!(const),
+                };
+                quote! {
+                    #(#attrs)*
+                    #vis #kind #ident: ::devsecrets::Id = #id_expr;
+                }
+            }
         })
     }
 }
@@ -53,3 +184,33 @@ pub fn devsecrets_id(t: TokenStream) -> TokenStream {
         .unwrap_or_else(|e| e.to_compile_error())
         .into()
 }
+
+/// Marks a test that needs secrets, skipping it when they are unavailable.
+///
+/// See `devsecrets::test` for details.
+#[proc_macro_attribute]
+pub fn test(attr: TokenStream, item: TokenStream) -> TokenStream {
+    test_attr::test_impl(attr.into(), item.into())
+        .unwrap_or_else(|e| e.to_compile_error())
+        .into()
+}
+
+/// Implements a `load()` function reading each field from a secret file.
+///
+/// See `devsecrets::DevSecrets` (the derive macro) for details.
+#[proc_macro_derive(DevSecrets, attributes(secret))]
+pub fn derive_devsecrets(input: TokenStream) -> TokenStream {
+    derive::derive_impl(input.into())
+        .unwrap_or_else(|e| e.to_compile_error())
+        .into()
+}
+
+/// Creates a `devsecrets::SecretPath`, validating the path at compile time.
+///
+/// See `devsecrets::secret_path` for details.
+#[proc_macro]
+pub fn secret_path(input: TokenStream) -> TokenStream {
+    secret_path::secret_path_impl(input.into())
+        .unwrap_or_else(|e| e.to_compile_error())
+        .into()
+}
@@ -0,0 +1,60 @@
+//! The `secret_path!` macro.
+
+use proc_macro2::{Span, TokenStream};
+use quote::quote;
+use std::path::{Path, PathBuf};
+
+/// Checks that `path` is listed in the secrets declared in the package manifest, if there are
+/// any. Returns the path of the manifest, which must be tracked even if it declares no secrets,
+/// so that a list added later is checked.
+fn check_declared(path: &syn::LitStr) -> syn::Result<Option<PathBuf>> {
+    let manifest_dir: PathBuf = match std::env::var_os("CARGO_MANIFEST_DIR") {
+        Some(manifest_dir) => manifest_dir.into(),
+        None => return Ok(None),
+    };
+    let manifest_path = manifest_dir.join("Cargo.toml");
+    let declared = devsecrets_core::read_declared_secrets(&manifest_dir)
+        .map_err(|e| syn::Error::new(Span::call_site(), e))?;
+    let declared = match declared {
+        Some(declared) => declared,
+        None => return Ok(Some(manifest_path)),
+    };
+    let value = path.value();
+    if !declared
+        .iter()
+        .any(|secret| Path::new(secret) == Path::new(&value))
+    {
+        return Err(syn::Error::new(
+            path.span(),
+            format!(
+                "{:?} is not declared in the `secrets` list of [package.metadata.devsecrets]. \
+                 Declared secrets: {}",
+                value,
+                declared.join(", ")
+            ),
+        ));
+    }
+    Ok(Some(manifest_path))
+}
+
+pub fn secret_path_impl(input: TokenStream) -> syn::Result<TokenStream> {
+    let path: syn::LitStr = syn::parse2(input)?;
+    devsecrets_core::check_relative_path(Path::new(&path.value()))
+        .map_err(|e| syn::Error::new(path.span(), e))?;
+
+    // Rebuild when the declared secrets change.
+    let track_manifest = check_declared(&path)?
+        .and_then(|manifest_path| manifest_path.to_str().map(str::to_string))
+        .map(|manifest_path| {
+            quote!(
+                const _: &[u8] = ::std::include_bytes!(#manifest_path);
+            )
+        });
+
+    Ok(quote! {
+        {
+            #track_manifest
+            ::devsecrets::SecretPath::new_unchecked(#path)
+        }
+    })
+}
@@ -0,0 +1,110 @@
+//! The `#[devsecrets::test]` attribute.
+
+use proc_macro2::TokenStream;
+use quote::quote;
+use std::path::Path;
+use syn::punctuated::Punctuated;
+
+/// The secret paths a test needs, such as `"api_key.txt", "db.json"`.
+struct SecretPaths(Punctuated<syn::LitStr, syn::Token![,]>);
+
+impl syn::parse::Parse for SecretPaths {
+    fn parse(stream: syn::parse::ParseStream) -> syn::parse::Result<Self> {
+        Ok(SecretPaths(Punctuated::parse_terminated(stream)?))
+    }
+}
+
+/// Returns true if `attr` is a test attribute of another framework, such as `#[rstest::test]`,
+/// which then takes the place of `#[test]`.
+fn is_test_attr(attr: &syn::Attribute) -> bool {
+    attr.path
+        .segments
+        .last()
+        .is_some_and(|segment| segment.ident == "test")
+}
+
+/// Returns the `expected` message of a `#[should_panic]` attribute, or an empty string if it does
+/// not have one.
+fn should_panic_expected(attr: &syn::Attribute) -> syn::Result<String> {
+    let lit = match attr.parse_meta()? {
+        syn::Meta::NameValue(syn::MetaNameValue { lit, .. }) => Some(lit),
+        syn::Meta::List(list) => list.nested.into_iter().find_map(|nested| match nested {
+            syn::NestedMeta::Meta(syn::Meta::NameValue(name_value))
+                if name_value.path.is_ident("expected") =>
+            {
+                Some(name_value.lit)
+            }
+            _ => None,
+        }),
+        syn::Meta::Path(_) => None,
+    };
+    match lit {
+        Some(syn::Lit::Str(lit)) => Ok(lit.value()),
+        Some(lit) => Err(syn::Error::new_spanned(
+            lit,
+            "expected a string for the `should_panic` message",
+        )),
+        None => Ok(String::new()),
+    }
+}
+
+pub fn test_impl(attr: TokenStream, item: TokenStream) -> syn::Result<TokenStream> {
+    let SecretPaths(paths) = syn::parse2(attr)?;
+    for path in &paths {
+        devsecrets_core::check_relative_path(Path::new(&path.value()))
+            .map_err(|e| syn::Error::new(path.span(), e))?;
+    }
+    let paths = paths.iter();
+
+    let syn::ItemFn {
+        attrs,
+        vis,
+        sig,
+        block,
+    } = syn::parse2(item)?;
+    if let Some(asyncness) = sig.asyncness {
+        return Err(syn::Error::new_spanned(
+            asyncness,
+            "#[devsecrets::test] does not support async tests",
+        ));
+    }
+    let name = &sig.ident;
+    let should_panic = attrs
+        .iter()
+        .find(|attr| attr.path.is_ident("should_panic"))
+        .map(should_panic_expected)
+        .transpose()?;
+    // Tests may return `()` or a `Result`; a skipped test returns success in either case.
+    let skip = match sig.output {
+        syn::ReturnType::Default => quote!(return),
+        syn::ReturnType::Type(..) => quote!(return ::std::result::Result::Ok(())),
+    };
+    let should_panic = match should_panic {
+        Some(expected) => quote!(::std::option::Option::Some(#expected)),
+        None => quote!(::std::option::Option::None),
+    };
+    // Another framework's test attribute already registers the test.
+    let test_attr = if attrs.iter().any(is_test_attr) {
+        quote!()
+    } else {
+        quote!(#[test])
+    };
+    let id_expr = crate::macro_impls::id_expr()?;
+    let stmts = &block.stmts;
+
+    Ok(quote! {
+        #(#attrs)*
+        #test_attr
+        #vis #sig {
+            if !::devsecrets::test_secrets_available(
+                &#id_expr,
+                &[#(#paths),*],
+                ::std::concat!(::std::module_path!(), "::", ::std::stringify!(#name)),
+                #should_panic,
+            ) {
+                #skip;
+            }
+            #(#stmts)*
+        }
+    })
+}
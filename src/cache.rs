@@ -0,0 +1,144 @@
+//! An opt-in cache of deserialized secret values.
+
+use crate::{Error, Result};
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+#[derive(PartialEq, Eq, Hash)]
+pub(crate) struct CacheKey {
+    path: PathBuf,
+    format: TypeId,
+    value: TypeId,
+}
+
+impl CacheKey {
+    pub(crate) fn new<F: 'static, T: 'static>(path: &Path) -> Self {
+        CacheKey {
+            path: path.to_path_buf(),
+            format: TypeId::of::<F>(),
+            value: TypeId::of::<T>(),
+        }
+    }
+}
+
+struct CacheEntry {
+    modified: SystemTime,
+    len: u64,
+    value: Arc<dyn Any + Send + Sync>,
+}
+
+/// Deserialized values, along with the modification time and size of the file they were read
+/// from.
+#[derive(Default)]
+pub(crate) struct ValueCache {
+    entries: Mutex<HashMap<CacheKey, CacheEntry>>,
+}
+
+impl ValueCache {
+    /// Returns the cached value for `key` if the file at `fullpath` has not changed since it was
+    /// loaded, or else loads and caches it with `load`.
+    pub(crate) fn get_or_load<T: Any + Send + Sync>(
+        &self,
+        fullpath: &Path,
+        key: CacheKey,
+        load: impl FnOnce() -> Result<T>,
+    ) -> Result<Arc<T>> {
+        let metadata = std::fs::metadata(fullpath).map_err(Error::FileError)?;
+        // Without a modification time, we can't tell whether the file changed.
+        let modified = match metadata.modified() {
+            Ok(modified) => modified,
+            Err(_) => return load().map(Arc::new),
+        };
+
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(entry) = entries.get(&key) {
+            if entry.modified == modified && entry.len == metadata.len() {
+                if let Ok(value) = entry.value.clone().downcast::<T>() {
+                    return Ok(value);
+                }
+            }
+        }
+        drop(entries);
+
+        // If the file changes while it is loaded, the recorded metadata is older than the value,
+        // so the next access loads it again.
+        let value = Arc::new(load()?);
+        entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries.insert(
+            key,
+            CacheEntry {
+                modified,
+                len: metadata.len(),
+                value: value.clone(),
+            },
+        );
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::TempSecretsDir;
+    use crate::JsonFormat;
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use std::time::{Duration, SystemTime};
+
+    type Config = HashMap<String, String>;
+
+    fn read(secrets: &crate::DevSecrets) -> Arc<Config> {
+        secrets
+            .read_from("config.json")
+            .with_format(JsonFormat)
+            .into_shared_value()
+            .unwrap()
+    }
+
+    #[test]
+    fn unchanged_files_are_not_parsed_again() {
+        let dir = TempSecretsDir::new();
+        dir.write("config.json", r#"{"token": "a"}"#);
+        let secrets = dir.secrets().with_cache();
+
+        let first = read(&secrets);
+        let second = read(&secrets);
+        assert!(Arc::ptr_eq(&first, &second));
+
+        // Without the cache, every read parses the file again.
+        let uncached = dir.secrets();
+        assert!(!Arc::ptr_eq(&read(&uncached), &read(&uncached)));
+    }
+
+    #[test]
+    fn files_with_a_new_length_are_read_again() {
+        let dir = TempSecretsDir::new();
+        dir.write("config.json", r#"{"token": "a"}"#);
+        let secrets = dir.secrets().with_cache();
+        assert_eq!(read(&secrets)["token"], "a");
+
+        dir.write("config.json", r#"{"token": "longer"}"#);
+        assert_eq!(read(&secrets)["token"], "longer");
+    }
+
+    #[test]
+    fn files_with_a_new_modification_time_are_read_again() {
+        let dir = TempSecretsDir::new();
+        dir.write("config.json", r#"{"token": "a"}"#);
+        let secrets = dir.secrets().with_cache();
+        assert_eq!(read(&secrets)["token"], "a");
+
+        // Same length, so only the modification time tells the versions apart.
+        dir.write("config.json", r#"{"token": "b"}"#);
+        let file = std::fs::File::options()
+            .write(true)
+            .open(dir.path().join("config.json"))
+            .unwrap();
+        file.set_modified(SystemTime::now() + Duration::from_secs(60))
+            .unwrap();
+        drop(file);
+        assert_eq!(read(&secrets)["token"], "b");
+    }
+}
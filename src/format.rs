@@ -57,3 +57,40 @@ impl Format for JsonFormat {
         serde_json::from_reader(reader)
     }
 }
+
+/// The TOML file format.
+///
+/// Used as input for `Source::with_format()` when the file format should be a
+/// TOML file.
+#[derive(Debug, Default)]
+pub struct TomlFormat;
+
+/// The error returned when deserializing with `TomlFormat`.
+#[derive(thiserror::Error, Debug)]
+pub enum TomlError {
+    /// The file could not be read.
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    /// The file is not valid TOML, or does not match the expected type.
+    #[error(transparent)]
+    Parse(#[from] toml::de::Error),
+}
+
+impl Format for TomlFormat {
+    type Error = TomlError;
+
+    fn extension(&self) -> &str {
+        "toml"
+    }
+
+    fn deserialize<T, R>(&self, mut reader: R) -> Result<T, Self::Error>
+    where
+        T: DeserializeOwned,
+        R: Read,
+    {
+        let mut contents = String::new();
+        reader.read_to_string(&mut contents)?;
+        Ok(toml::from_str(&contents)?)
+    }
+}
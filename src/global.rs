@@ -0,0 +1,162 @@
+//! A lazily-initialized, process-wide `DevSecrets` handle.
+
+use crate::{DevSecrets, Error, Id};
+use std::sync::{Arc, OnceLock, RwLock};
+
+/// A `DevSecrets` handle that is created on first use and shared by the whole process.
+///
+/// Declare it as a static next to your ID:
+///
+/// ```text
+/// devsecrets::import_id!(static ID);
+/// static SECRETS: devsecrets::GlobalSecrets = devsecrets::GlobalSecrets::new(&ID);
+///
+/// let api_key = SECRETS.get()?.read_from("api_key.txt").to_string()?;
+/// ```
+///
+/// The devsecrets directory is looked up once. If that fails, the same error is returned by every
+/// later call to `get()`.
+pub struct GlobalSecrets {
+    id: &'static Id,
+    secrets: OnceLock<Result<Arc<DevSecrets>, Arc<Error>>>,
+    override_secrets: RwLock<Option<Arc<DevSecrets>>>,
+}
+
+impl GlobalSecrets {
+    /// Creates a handle for the secrets of the given ID, without looking them up yet.
+    pub const fn new(id: &'static Id) -> Self {
+        GlobalSecrets {
+            id,
+            secrets: OnceLock::new(),
+            override_secrets: RwLock::new(None),
+        }
+    }
+
+    /// Returns the shared `DevSecrets`, looking up the directory if this is the first call.
+    ///
+    /// If an override is set with `set_override()`, it is returned instead.
+    pub fn get(&self) -> Result<Arc<DevSecrets>, Arc<Error>> {
+        self.get_or_init_with(|| DevSecrets::from_id(self.id))
+    }
+
+    /// Like `get()`, but calls `init` instead of `DevSecrets::from_id()` if this is the first call.
+    fn get_or_init_with(
+        &self,
+        init: impl FnOnce() -> Result<DevSecrets, Error>,
+    ) -> Result<Arc<DevSecrets>, Arc<Error>> {
+        if let Some(secrets) = &*self
+            .override_secrets
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+        {
+            return Ok(secrets.clone());
+        }
+        self.secrets
+            .get_or_init(|| init().map(Arc::new).map_err(Arc::new))
+            .clone()
+    }
+
+    /// Makes `get()` return `secrets` until the returned guard is dropped, such as secrets read
+    /// from a fixture directory with `DevSecrets::from_dir()`.
+    ///
+    /// The override applies to the whole process, so tests that set different overrides on the
+    /// same handle should not run concurrently.
+    pub fn set_override(&self, secrets: DevSecrets) -> OverrideGuard<'_> {
+        let previous = self
+            .override_secrets
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .replace(Arc::new(secrets));
+        OverrideGuard {
+            global: self,
+            previous,
+        }
+    }
+}
+
+/// Restores the previous override of a `GlobalSecrets` when dropped.
+#[must_use = "The override is removed when the guard is dropped"]
+pub struct OverrideGuard<'a> {
+    global: &'a GlobalSecrets,
+    previous: Option<Arc<DevSecrets>>,
+}
+
+impl Drop for OverrideGuard<'_> {
+    fn drop(&mut self) {
+        *self
+            .global
+            .override_secrets
+            .write()
+            .unwrap_or_else(|e| e.into_inner()) = self.previous.take();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::internal_core::DevSecretsId;
+    use crate::test_util::TempSecretsDir;
+    use std::borrow::Cow;
+    use std::cell::Cell;
+
+    static ID: Id = Id(DevSecretsId(Cow::Borrowed(
+        "00000000-0000-0000-0000-000000000000",
+    )));
+
+    fn read_key(secrets: &DevSecrets) -> String {
+        secrets.read_from("key.txt").to_string().unwrap()
+    }
+
+    #[test]
+    fn initializes_once() {
+        let global = GlobalSecrets::new(&ID);
+        let dir = TempSecretsDir::new();
+        let calls = Cell::new(0);
+        let init = || {
+            calls.set(calls.get() + 1);
+            Ok(dir.secrets())
+        };
+
+        let first = global.get_or_init_with(init).unwrap();
+        let second = global.get_or_init_with(init).unwrap();
+        assert_eq!(calls.get(), 1);
+        assert!(Arc::ptr_eq(&first, &second));
+    }
+
+    #[test]
+    fn failed_initialization_returns_the_same_error() {
+        let global = GlobalSecrets::new(&ID);
+        let dir = TempSecretsDir::new();
+
+        let first = global
+            .get_or_init_with(|| Err(Error::DirectoryNotInitialized))
+            .err()
+            .unwrap();
+        let second = global.get_or_init_with(|| Ok(dir.secrets())).err().unwrap();
+        assert!(matches!(*first, Error::DirectoryNotInitialized));
+        assert!(Arc::ptr_eq(&first, &second));
+    }
+
+    #[test]
+    fn override_is_removed_when_the_guard_is_dropped() {
+        let global = GlobalSecrets::new(&ID);
+        let original = TempSecretsDir::new();
+        original.write("key.txt", "original");
+        let outer = TempSecretsDir::new();
+        outer.write("key.txt", "outer");
+        let inner = TempSecretsDir::new();
+        inner.write("key.txt", "inner");
+        let get = || global.get_or_init_with(|| Ok(original.secrets())).unwrap();
+
+        {
+            let _outer = global.set_override(outer.secrets());
+            assert_eq!(read_key(&global.get().unwrap()), "outer");
+            {
+                let _inner = global.set_override(inner.secrets());
+                assert_eq!(read_key(&get()), "inner");
+            }
+            assert_eq!(read_key(&get()), "outer");
+        }
+        assert_eq!(read_key(&get()), "original");
+    }
+}
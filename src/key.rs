@@ -0,0 +1,103 @@
+//! Typed keys for secret files.
+
+use crate::format::Format;
+use crate::path::SecretPath;
+use std::fmt;
+use std::marker::PhantomData;
+use std::path::Path;
+
+/// A secret file, along with its format and the type it is deserialized into.
+///
+/// Keys are usually declared once as constants, and read with `DevSecrets::get()`:
+///
+/// ```text
+/// use devsecrets::{SecretKey, TomlFormat};
+///
+/// const DB: SecretKey<DbConfig, TomlFormat> = SecretKey::new("db.toml");
+///
+/// let db = secrets.get(&DB)?;
+/// ```
+pub struct SecretKey<T, F: Format> {
+    path: &'static str,
+    // `fn() -> _` keeps the key `Send`, `Sync` and `Copy` regardless of `T` and `F`.
+    marker: PhantomData<fn() -> (T, F)>,
+}
+
+impl<T, F: Format> SecretKey<T, F> {
+    /// Creates a key for the file at the given relative path. The path is checked when the key is
+    /// used.
+    pub const fn new(path: &'static str) -> Self {
+        SecretKey {
+            path,
+            marker: PhantomData,
+        }
+    }
+
+    /// Creates a key for a path checked at compile time by `secret_path!()`.
+    pub const fn from_path(path: SecretPath) -> Self {
+        SecretKey::new(path.as_str())
+    }
+
+    /// Returns the relative path of the secret file.
+    pub fn path(&self) -> &Path {
+        Path::new(self.path)
+    }
+}
+
+impl<T, F: Format> Clone for SecretKey<T, F> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T, F: Format> Copy for SecretKey<T, F> {}
+
+impl<T, F: Format> fmt::Debug for SecretKey<T, F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("SecretKey").field(&self.path).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempSecretsDir;
+    use crate::TomlFormat;
+    use serde::Deserialize;
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct DbConfig {
+        url: String,
+    }
+
+    const DB: SecretKey<DbConfig, TomlFormat> = SecretKey::new("config/db.toml");
+
+    #[test]
+    fn reads_a_const_key() {
+        let dir = TempSecretsDir::new();
+        dir.write("config/db.toml", "url = \"pg://\"");
+        let secrets = dir.secrets();
+
+        assert_eq!(secrets.get(&DB).unwrap().url, "pg://");
+        assert_eq!(secrets.get_opt(&DB).unwrap().unwrap().url, "pg://");
+    }
+
+    #[test]
+    fn get_opt_returns_none_for_missing_files() {
+        let dir = TempSecretsDir::new();
+        let secrets = dir.secrets();
+
+        assert!(secrets.get(&DB).err().unwrap().is_not_found());
+        assert_eq!(secrets.get_opt(&DB).unwrap(), None);
+    }
+
+    #[test]
+    fn get_opt_returns_parse_errors() {
+        let dir = TempSecretsDir::new();
+        dir.write("config/db.toml", "url =");
+        let secrets = dir.secrets();
+
+        let error = secrets.get_opt(&DB).err().unwrap();
+        assert!(!error.is_not_found());
+    }
+}
@@ -3,12 +3,27 @@
 //!
 //! # Devsecret
 
+// Lets the crate's own tests use macros that expand to `::devsecrets::` paths.
+#[cfg(test)]
+extern crate self as devsecrets;
+
+mod cache;
 mod format;
+mod global;
+mod key;
+mod load;
+mod path;
+#[cfg(test)]
+mod test_util;
+mod testing;
+#[cfg(feature = "watch")]
+mod watch;
 
 use serde::de::DeserializeOwned;
 use std::error::Error as StdError;
 use std::io;
-use std::path::{Component, Path, PathBuf};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 // Re-export the devsecrets_id macro to make it available to users.
 
@@ -30,16 +45,120 @@ use std::path::{Component, Path, PathBuf};
 /// import_id!(pub SECRET_ID);
 /// ```
 ///
+/// Attributes and doc comments are passed through to the declaration, and `const` can be given to
+/// declare a constant instead of a static:
+///
+/// ```text
+/// import_id!(
+///     /// The ID of this crate's secrets.
+///     pub(crate) const SECRET_ID
+/// );
+/// ```
+///
+/// With no arguments, the macro expands to a `devsecrets::Id` expression instead:
+///
+/// ```text
+/// let id = import_id!();
+/// ```
+///
 /// This macro reads the value of your devsecrets ID file at compile time. It
 /// will fail if that file does not exist in your project, but will otherwise
 /// succeed, even if the devsecrets directory has not been created in your
-/// current environment.
+/// current environment. Changes to the file the ID was read from, your crate's `Cargo.toml` or
+/// the workspace manifest cause your crate to be rebuilt. Creating a new ID file that takes
+/// precedence does not, since files that don't exist yet can't be tracked; run
+/// `cargo clean -p <crate>` or touch a source file in that case. See the README for details.
+///
+/// If your crate has no ID file of its own, the ID is read from the `[package.metadata.devsecrets]`
+/// table of its `Cargo.toml`, and failing that, the ID shared by its workspace is used; see
+/// `cargo devsecrets init --in-manifest` and `cargo devsecrets init --workspace-shared`.
 pub use devsecrets_macros::devsecrets_id as import_id;
 
+/// Marks a test that needs secrets, skipping it if they are unavailable.
+///
+/// Use this in place of `#[test]`, listing the relative paths of the secret files the test reads:
+///
+/// ```text
+/// #[devsecrets::test("api_key.txt", "sandbox.json")]
+/// fn talks_to_sandbox() {
+///     // ...
+/// }
+/// ```
+///
+/// If the devsecrets directory has not been initialized, or any of the files are missing, the
+/// test prints a "Skipping test" line to stderr, which is shown even though test output is
+/// captured, and returns without running its body. Stable Rust can't mark a test as ignored at
+/// runtime, so the skipped test is reported as passed. Tests with `#[should_panic]` are skipped by
+/// panicking with the expected message, so they pass too.
+/// Set the `DEVSECRETS_REQUIRE_SECRETS` environment variable to make such tests fail instead,
+/// for example in CI jobs that are expected to have the secrets.
+///
+/// The test may return `()` or a `Result`. The paths are checked for validity at compile time.
+/// If another test attribute ending in `test`, such as `#[rstest::test]`, is also given, it is
+/// used in place of `#[test]`. Async tests, such as those using `#[tokio::test]`, are not
+/// supported.
+pub use devsecrets_macros::test;
+
+/// Derives a `load()` function that reads each field of a struct from a secret file.
+///
+/// Each field is annotated with the relative path of its file, and optionally its format:
+///
+/// ```text
+/// #[derive(devsecrets::DevSecrets)]
+/// struct Config {
+///     #[secret(path = "db.toml")]
+///     db: DbConfig,
+///     #[secret(path = "api_key.txt")]
+///     api_key: String,
+///     #[secret(path = "signing.key", format = "bytes")]
+///     signing_key: Option<Vec<u8>>,
+/// }
+///
+/// let config = Config::load(&secrets)?;
+/// ```
+///
+/// The format is one of `"json"`, `"toml"`, `"text"` (a `String`) or `"bytes"` (a `Vec<u8>`).
+/// If it is not given, `.json` and `.toml` files are deserialized with serde, and other files are
+/// read as text. Fields of type `Option<T>` are `None` if their file does not exist.
+///
+/// The generated `load(&DevSecrets)` returns a `LoadError` listing every field that failed,
+/// rather than stopping at the first one.
+pub use devsecrets_macros::DevSecrets;
+
+/// Creates a `SecretPath`, checking at compile time that it is a valid relative path.
+///
+/// ```text
+/// use devsecrets::secret_path;
+///
+/// const DB_CONFIG: devsecrets::SecretPath = secret_path!("db/config.json");
+///
+/// secrets.read_from(&DB_CONFIG).to_string()?;
+/// ```
+///
+/// Absolute paths and paths with `..` or `.` components are rejected, the same as at runtime.
+///
+/// If your crate lists its secrets in `Cargo.toml`, the path must also be one of them:
+///
+/// ```toml
+/// [package.metadata.devsecrets]
+/// secrets = ["db/config.json", "api_key.txt"]
+/// ```
+pub use devsecrets_macros::secret_path;
+
 #[doc(hidden)]
 pub use devsecrets_core as internal_core;
 
-pub use format::{Format, JsonFormat};
+#[doc(hidden)]
+pub use testing::test_secrets_available;
+pub use testing::REQUIRE_SECRETS_ENV_VAR;
+
+pub use format::{Format, JsonFormat, TomlError, TomlFormat};
+pub use global::{GlobalSecrets, OverrideGuard};
+pub use key::SecretKey;
+pub use load::LoadError;
+pub use path::SecretPath;
+#[cfg(feature = "watch")]
+pub use watch::WatchedValue;
 
 /// An opaque devsecrets ID for a project.
 ///
@@ -87,11 +206,21 @@ pub enum Error {
     IoError(#[from] io::Error),
 }
 
+impl Error {
+    /// Returns true if the error occurred because the secret file does not exist.
+    pub fn is_not_found(&self) -> bool {
+        match self {
+            Error::FileError(e) | Error::IoError(e) => e.kind() == io::ErrorKind::NotFound,
+            _ => false,
+        }
+    }
+}
+
 fn check_extension(p: &Path, ext: &str) -> Result<()> {
     if p.extension() != Some(std::ffi::OsStr::new(ext)) {
         return Err(Error::InvalidExtension(format!(
-            "Path {:?} must have a .json extension.",
-            p
+            "Path {:?} must have a .{} extension.",
+            p, ext
         )));
     }
 
@@ -100,6 +229,25 @@ fn check_extension(p: &Path, ext: &str) -> Result<()> {
 
 type Result<T> = std::result::Result<T, Error>;
 
+/// Deserializes the file at `fullpath` with `format`.
+fn deserialize_file<T: DeserializeOwned, F: Format>(format: &F, fullpath: &Path) -> Result<T> {
+    let file = std::fs::File::open(fullpath).map_err(Error::FileError)?;
+    deserialize_reader(format, file)
+}
+
+fn deserialize_reader<T: DeserializeOwned, F: Format, R: io::Read>(
+    format: &F,
+    reader: R,
+) -> Result<T> {
+    format
+        .deserialize::<T, R>(reader)
+        .map_err(|e: F::Error| Error::ParseError(Box::new(e)))
+}
+
+fn bytes_to_string(contents: Vec<u8>) -> Result<String> {
+    String::from_utf8(contents).map_err(|e| Error::ParseError(Box::new(e)))
+}
+
 /// Used to access the files inside of the devsecrets directory for your project.
 ///
 /// This can be obtained by calling `DevSecrets::from_id(&ID)` with a devsecrets
@@ -109,6 +257,7 @@ type Result<T> = std::result::Result<T, Error>;
 /// `cargo devsecrets` tool to help with that.
 pub struct DevSecrets {
     dir: devsecrets_core::DevSecretsDir,
+    cache: Option<cache::ValueCache>,
 }
 
 impl DevSecrets {
@@ -127,7 +276,35 @@ impl DevSecrets {
             .get_child(&id.0)?
             .ok_or(Error::DirectoryNotInitialized)?;
 
-        Ok(DevSecrets { dir: child })
+        Ok(DevSecrets {
+            dir: child,
+            cache: None,
+        })
+    }
+
+    /// Create a `DevSecrets` instance reading from the given directory, instead of the directory
+    /// for a devsecrets ID.
+    ///
+    /// This is mostly useful in tests, to read secrets from a directory of fixtures.
+    pub fn from_dir(dir: impl Into<PathBuf>) -> Self {
+        DevSecrets {
+            dir: devsecrets_core::DevSecretsDir::from_path(dir),
+            cache: None,
+        }
+    }
+
+    /// Enables caching of the values returned by `SourceWithFormat::into_shared_value()` and
+    /// `get_shared()`.
+    ///
+    /// Cached values are keyed by path, format and type, and are read again if the file's
+    /// modification time or size changes.
+    ///
+    /// Only the shared accessors use the cache. `into_value()` and `get()` return an owned value,
+    /// so they still read and parse the file on every call; switch hot paths to
+    /// `into_shared_value()` or `get_shared()` to benefit from caching.
+    pub fn with_cache(mut self) -> Self {
+        self.cache = Some(cache::ValueCache::default());
+        self
     }
 
     fn root_dir(&self) -> &Path {
@@ -136,33 +313,14 @@ impl DevSecrets {
 
     fn get_relative_path(&self, relpath: impl AsRef<Path>) -> Result<PathBuf> {
         let relpath = relpath.as_ref();
-        if relpath.is_absolute() {
-            return Err(Error::InvalidRelativePath(format!(
-                "Path {:?} must not be absolute.",
-                relpath
-            )));
-        }
-
-        // Check that we only have normal parts of the path
-        for component in relpath.components() {
-            match component {
-                Component::Normal(_) => (),
-                _ => {
-                    return Err(Error::InvalidRelativePath(format!(
-                        "Path {:?} has a non-normal component.",
-                        relpath
-                    )))
-                }
-            }
-        }
-
+        devsecrets_core::check_relative_path(relpath).map_err(Error::InvalidRelativePath)?;
         Ok(self.root_dir().join(relpath))
     }
 
     fn make_reader_inner(&self, path: impl AsRef<Path>) -> Result<std::fs::File> {
         let path = path.as_ref();
         let fullpath = self.get_relative_path(path)?;
-        Ok(std::fs::File::open(fullpath).map_err(Error::FileError)?)
+        std::fs::File::open(fullpath).map_err(Error::FileError)
     }
 
     fn read(&self, path: impl AsRef<Path>) -> Result<Vec<u8>> {
@@ -173,9 +331,13 @@ impl DevSecrets {
     }
 
     fn read_str(&self, path: impl AsRef<Path>) -> Result<String> {
-        let contents = self.read(path)?;
-        let string = String::from_utf8(contents).map_err(|e| Error::ParseError(Box::new(e)))?;
-        Ok(string)
+        bytes_to_string(self.read(path)?)
+    }
+
+    #[cfg(feature = "async")]
+    async fn read_async(&self, path: &Path) -> Result<Vec<u8>> {
+        let fullpath = self.get_relative_path(path)?;
+        tokio::fs::read(fullpath).await.map_err(Error::FileError)
     }
 
     /// Indicates that data should be read from the given path.
@@ -197,6 +359,42 @@ impl DevSecrets {
             path: path.as_ref(),
         }
     }
+
+    /// Reads and deserializes the secret file described by `key`.
+    pub fn get<T, F>(&self, key: &SecretKey<T, F>) -> Result<T>
+    where
+        T: DeserializeOwned,
+        F: Format + Default,
+    {
+        self.read_from(key.path())
+            .with_format(F::default())
+            .into_value()
+    }
+
+    /// Like `get()`, but returns a shared value that is cached if caching is enabled with
+    /// `with_cache()`.
+    pub fn get_shared<T, F>(&self, key: &SecretKey<T, F>) -> Result<Arc<T>>
+    where
+        T: DeserializeOwned + Send + Sync + 'static,
+        F: Format + Default + 'static,
+    {
+        self.read_from(key.path())
+            .with_format(F::default())
+            .into_shared_value()
+    }
+
+    /// Like `get()`, but returns `Ok(None)` if the secret file does not exist.
+    pub fn get_opt<T, F>(&self, key: &SecretKey<T, F>) -> Result<Option<T>>
+    where
+        T: DeserializeOwned,
+        F: Format + Default,
+    {
+        match self.get(key) {
+            Ok(value) => Ok(Some(value)),
+            Err(e) if e.is_not_found() => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
 }
 
 /// An intermediate type created from `DevSecrets::read_from()`.
@@ -233,6 +431,22 @@ impl<'a> Source<'a> {
     pub fn to_string(&self) -> Result<String> {
         self.secrets.read_str(self.path)
     }
+
+    /// Like `to_bytes()`, but reads the file without blocking the current task.
+    ///
+    /// Must be called from within a tokio runtime.
+    #[cfg(feature = "async")]
+    pub async fn to_bytes_async(&self) -> Result<Vec<u8>> {
+        self.secrets.read_async(self.path).await
+    }
+
+    /// Like `to_string()`, but reads the file without blocking the current task.
+    ///
+    /// Must be called from within a tokio runtime.
+    #[cfg(feature = "async")]
+    pub async fn to_string_async(&self) -> Result<String> {
+        bytes_to_string(self.secrets.read_async(self.path).await?)
+    }
 }
 
 /// An intermediate type created from `Source::with_format()`.
@@ -250,11 +464,99 @@ where
     F: Format,
 {
     /// Deserializes the indicated file using the indicated format of type `T`.
+    ///
+    /// The file is read and parsed on every call, even if caching is enabled with
+    /// `DevSecrets::with_cache()`; use `into_shared_value()` to read cached values.
     pub fn into_value<T: DeserializeOwned>(&self) -> Result<T> {
-        check_extension(self.path, self.format.extension().as_ref())?;
-        Ok(self
-            .format
-            .deserialize::<T, std::fs::File>(self.secrets.make_reader_inner(self.path)?)
-            .map_err(|e: F::Error| Error::ParseError(Box::new(e)))?)
+        check_extension(self.path, self.format.extension())?;
+        deserialize_file(&self.format, &self.secrets.get_relative_path(self.path)?)
+    }
+
+    /// Like `into_value()`, but reads the file without blocking the current task.
+    ///
+    /// Must be called from within a tokio runtime.
+    #[cfg(feature = "async")]
+    pub async fn into_value_async<T: DeserializeOwned>(&self) -> Result<T> {
+        check_extension(self.path, self.format.extension())?;
+        let contents = self.secrets.read_async(self.path).await?;
+        deserialize_reader(&self.format, contents.as_slice())
+    }
+
+    /// Deserializes the indicated file, and keeps the value up to date as the file changes.
+    ///
+    /// Fails if the initial value can't be read. See `WatchedValue` for details.
+    #[cfg(feature = "watch")]
+    pub fn watch<T>(&self) -> Result<WatchedValue<T>>
+    where
+        T: DeserializeOwned + Send + Sync + 'static,
+        F: Default + Send + 'static,
+    {
+        check_extension(self.path, self.format.extension())?;
+        WatchedValue::new::<F>(self.secrets.get_relative_path(self.path)?)
+    }
+
+    /// Deserializes the indicated file like `into_value()`, returning a shared value.
+    ///
+    /// If caching is enabled with `DevSecrets::with_cache()`, the value is only read and parsed
+    /// again when the file has changed.
+    pub fn into_shared_value<T>(&self) -> Result<Arc<T>>
+    where
+        T: DeserializeOwned + Send + Sync + 'static,
+        F: 'static,
+    {
+        match &self.secrets.cache {
+            Some(cache) => {
+                check_extension(self.path, self.format.extension())?;
+                let fullpath = self.secrets.get_relative_path(self.path)?;
+                cache.get_or_load(&fullpath, cache::CacheKey::new::<F, T>(self.path), || {
+                    self.into_value()
+                })
+            }
+            None => self.into_value().map(Arc::new),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "async"))]
+mod async_tests {
+    use super::*;
+    use crate::test_util::TempSecretsDir;
+
+    #[derive(serde::Deserialize, Debug, PartialEq)]
+    struct DbConfig {
+        url: String,
+    }
+
+    #[tokio::test]
+    async fn async_reads_match_sync_reads() {
+        let dir = TempSecretsDir::new();
+        dir.write("key.txt", "secret\n");
+        dir.write("db.toml", "url = \"pg://\"");
+        let secrets = dir.secrets();
+
+        let key = secrets.read_from("key.txt");
+        assert_eq!(key.to_bytes_async().await.unwrap(), key.to_bytes().unwrap());
+        assert_eq!(
+            key.to_string_async().await.unwrap(),
+            key.to_string().unwrap()
+        );
+        let db = secrets.read_from("db.toml").with_format(TomlFormat);
+        assert_eq!(
+            db.into_value_async::<DbConfig>().await.unwrap(),
+            db.into_value::<DbConfig>().unwrap()
+        );
+
+        let missing = secrets.read_from("missing.txt");
+        let async_error = missing.to_bytes_async().await.err().unwrap();
+        let sync_error = missing.to_bytes().err().unwrap();
+        assert!(async_error.is_not_found());
+        assert_eq!(async_error.to_string(), sync_error.to_string());
+        let async_error = missing.to_string_async().await.err().unwrap();
+        assert!(async_error.is_not_found());
+        let missing = secrets.read_from("missing.json").with_format(JsonFormat);
+        let async_error = missing.into_value_async::<DbConfig>().await.err().unwrap();
+        let sync_error = missing.into_value::<DbConfig>().err().unwrap();
+        assert!(async_error.is_not_found());
+        assert_eq!(async_error.to_string(), sync_error.to_string());
     }
 }
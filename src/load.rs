@@ -0,0 +1,132 @@
+//! Support for `#[derive(DevSecrets)]`.
+
+use crate::Error;
+use std::fmt;
+use std::path::PathBuf;
+
+/// The error returned by the `load()` function generated by `#[derive(DevSecrets)]`.
+///
+/// Contains an error for every field that could not be loaded, rather than just the first.
+#[derive(Debug)]
+pub struct LoadError {
+    errors: Vec<(PathBuf, Error)>,
+}
+
+impl LoadError {
+    #[doc(hidden)]
+    pub fn from_errors(errors: Vec<(PathBuf, Error)>) -> Self {
+        LoadError { errors }
+    }
+
+    /// Returns the relative path of each secret that failed to load, with its error.
+    pub fn errors(&self) -> &[(PathBuf, Error)] {
+        &self.errors
+    }
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Could not load {} secret(s):", self.errors.len())?;
+        for (path, error) in &self.errors {
+            write!(f, "\n  {}: {}", path.display(), error)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::TempSecretsDir;
+    use serde::Deserialize;
+    use std::path::Path;
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Database {
+        url: String,
+    }
+
+    // The field names match locals the derive used to bind, to check they no longer clash.
+    #[derive(crate::DevSecrets, Debug)]
+    struct Secrets {
+        #[secret(path = "config.json")]
+        secrets: Database,
+        #[secret(path = "db.toml")]
+        errors: Database,
+        #[secret(path = "key.txt")]
+        value: String,
+        #[secret(path = "key.bin", format = "bytes")]
+        e: Vec<u8>,
+        #[secret(path = "optional.txt")]
+        optional: Option<String>,
+    }
+
+    fn write_required(dir: &TempSecretsDir) {
+        dir.write("config.json", r#"{ "url": "json://" }"#);
+        dir.write("db.toml", "url = \"toml://\"");
+        dir.write("key.txt", "text");
+        dir.write("key.bin", [0u8, 159, 146, 150]);
+    }
+
+    #[test]
+    fn loads_each_format() {
+        let dir = TempSecretsDir::new();
+        write_required(&dir);
+        dir.write("optional.txt", "present");
+
+        let loaded = Secrets::load(&dir.secrets()).unwrap();
+        assert_eq!(loaded.secrets.url, "json://");
+        assert_eq!(loaded.errors.url, "toml://");
+        assert_eq!(loaded.value, "text");
+        assert_eq!(loaded.e, [0, 159, 146, 150]);
+        assert_eq!(loaded.optional.as_deref(), Some("present"));
+    }
+
+    #[test]
+    fn missing_optional_fields_are_none() {
+        let dir = TempSecretsDir::new();
+        write_required(&dir);
+
+        let loaded = Secrets::load(&dir.secrets()).unwrap();
+        assert_eq!(loaded.optional, None);
+    }
+
+    #[test]
+    fn optional_fields_still_report_other_errors() {
+        let dir = TempSecretsDir::new();
+        write_required(&dir);
+        dir.write("optional.txt", [0xff, 0xfe]);
+
+        let error = Secrets::load(&dir.secrets()).unwrap_err();
+        assert_eq!(error.errors().len(), 1);
+        assert_eq!(error.errors()[0].0, Path::new("optional.txt"));
+        assert!(!error.errors()[0].1.is_not_found());
+    }
+
+    #[test]
+    fn reports_every_failed_field() {
+        let dir = TempSecretsDir::new();
+        dir.write("config.json", "not json");
+        dir.write("key.txt", "text");
+
+        let error = Secrets::load(&dir.secrets()).unwrap_err();
+        let paths: Vec<_> = error
+            .errors()
+            .iter()
+            .map(|(path, _)| path.as_path())
+            .collect();
+        assert_eq!(
+            paths,
+            [
+                Path::new("config.json"),
+                Path::new("db.toml"),
+                Path::new("key.bin")
+            ]
+        );
+        assert!(!error.errors()[0].1.is_not_found());
+        assert!(error.errors()[1].1.is_not_found());
+        assert!(error.errors()[2].1.is_not_found());
+        assert!(error.to_string().starts_with("Could not load 3 secret(s):"));
+    }
+}
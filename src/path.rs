@@ -0,0 +1,34 @@
+//! Secret paths validated at compile time.
+
+use std::fmt;
+use std::path::Path;
+
+/// The relative path of a secret file, checked at compile time by `secret_path!()`.
+///
+/// Can be passed anywhere a path is accepted, such as `DevSecrets::read_from()`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SecretPath(&'static str);
+
+impl SecretPath {
+    #[doc(hidden)]
+    pub const fn new_unchecked(path: &'static str) -> Self {
+        SecretPath(path)
+    }
+
+    /// Returns the path as a string.
+    pub const fn as_str(&self) -> &'static str {
+        self.0
+    }
+}
+
+impl AsRef<Path> for SecretPath {
+    fn as_ref(&self) -> &Path {
+        Path::new(self.0)
+    }
+}
+
+impl fmt::Display for SecretPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.0)
+    }
+}
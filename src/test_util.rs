@@ -0,0 +1,41 @@
+//! Helpers for the crate's tests.
+
+use crate::DevSecrets;
+use std::path::{Path, PathBuf};
+
+/// A temporary secrets directory that is removed when dropped.
+pub(crate) struct TempSecretsDir {
+    path: PathBuf,
+}
+
+impl TempSecretsDir {
+    pub(crate) fn new() -> Self {
+        let path = std::env::temp_dir().join(format!(
+            "devsecrets-test-{}",
+            devsecrets_core::DevSecretsId::new_unique().id_str()
+        ));
+        std::fs::create_dir_all(&path).unwrap();
+        TempSecretsDir { path }
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Writes a secret file, creating its parent directories.
+    pub(crate) fn write(&self, relpath: &str, contents: impl AsRef<[u8]>) {
+        let path = self.path.join(relpath);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, contents).unwrap();
+    }
+
+    pub(crate) fn secrets(&self) -> DevSecrets {
+        DevSecrets::from_dir(&self.path)
+    }
+}
+
+impl Drop for TempSecretsDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
@@ -0,0 +1,137 @@
+//! Support for the `#[devsecrets::test]` attribute.
+
+use crate::{DevSecrets, Error, Id};
+use std::io::Write;
+
+/// The environment variable that makes tests fail, rather than skip, when secrets are missing.
+pub const REQUIRE_SECRETS_ENV_VAR: &str = "DEVSECRETS_REQUIRE_SECRETS";
+
+/// Returns a description of why the secrets at `paths` can't be read, or `None` if they can.
+fn missing_secrets(id: &Id, paths: &[&str]) -> Option<String> {
+    let secrets =
+        match DevSecrets::from_id(id) {
+            Ok(secrets) => secrets,
+            Err(Error::DirectoryNotInitialized) => return Some(
+                "the devsecrets directory has not been initialized; run `cargo devsecrets init`"
+                    .to_string(),
+            ),
+            Err(e) => {
+                return Some(format!(
+                    "the devsecrets directory could not be opened: {}",
+                    e
+                ))
+            }
+        };
+    let missing: Vec<_> = paths
+        .iter()
+        .filter(|path| !secrets.root_dir().join(path).is_file())
+        .copied()
+        .collect();
+    if missing.is_empty() {
+        return None;
+    }
+    Some(format!(
+        "missing {} in {}",
+        missing.join(", "),
+        secrets.root_dir().display()
+    ))
+}
+
+/// Checks whether a test's secrets are available, returning false if the test should return
+/// without running its body.
+///
+/// `should_panic` is the expected message of a `#[should_panic]` test, or an empty string if it
+/// has none. Such a test passes if it panics, so it is skipped by panicking with the expected
+/// message, and failed by returning without panicking.
+///
+/// Other tests are skipped by returning, and failed by panicking. Tests are failed instead of
+/// skipped if the `DEVSECRETS_REQUIRE_SECRETS` environment variable is set.
+#[doc(hidden)]
+pub fn test_secrets_available(
+    id: &Id,
+    paths: &[&str],
+    test_name: &str,
+    should_panic: Option<&str>,
+) -> bool {
+    let required = std::env::var_os(REQUIRE_SECRETS_ENV_VAR).is_some_and(|value| !value.is_empty());
+    match missing_secrets(id, paths) {
+        Some(reason) => check_missing(&reason, test_name, should_panic, required),
+        None => true,
+    }
+}
+
+fn check_missing(
+    reason: &str,
+    test_name: &str,
+    should_panic: Option<&str>,
+    required: bool,
+) -> bool {
+    if required {
+        let message = format!(
+            "Secrets for test {} are unavailable: {} ({} is set)",
+            test_name, reason, REQUIRE_SECRETS_ENV_VAR
+        );
+        if should_panic.is_some() {
+            // Shown by the harness with the "did not panic" failure.
+            eprintln!("{}", message);
+            return false;
+        }
+        panic!("{}", message);
+    }
+    // Written to stderr directly rather than with `eprintln!`, which the test harness captures
+    // and only shows for failed tests.
+    let _ = writeln!(
+        std::io::stderr(),
+        "Skipping test {}: {}. Set {}=1 to fail instead.",
+        test_name,
+        reason,
+        REQUIRE_SECRETS_ENV_VAR
+    );
+    if let Some(expected) = should_panic {
+        panic!("Skipped because secrets are unavailable. {}", expected);
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::panic::{catch_unwind, UnwindSafe};
+
+    fn panic_message(f: impl FnOnce() -> bool + UnwindSafe) -> Option<String> {
+        let payload = catch_unwind(f).err()?;
+        Some(match payload.downcast::<String>() {
+            Ok(message) => *message,
+            Err(payload) => payload.downcast_ref::<&str>().unwrap().to_string(),
+        })
+    }
+
+    #[test]
+    fn skips_missing_secrets() {
+        assert!(!check_missing("missing a.txt", "t", None, false));
+    }
+
+    #[test]
+    fn skips_should_panic_tests_by_panicking_with_the_expected_message() {
+        let message = panic_message(|| check_missing("missing a.txt", "t", Some("boom"), false));
+        assert!(message.unwrap().contains("boom"));
+    }
+
+    #[test]
+    fn required_secrets_fail_tests() {
+        let message = panic_message(|| check_missing("missing a.txt", "t", None, true));
+        assert!(message.unwrap().contains("missing a.txt"));
+    }
+
+    #[test]
+    fn required_secrets_fail_should_panic_tests_by_returning() {
+        assert_eq!(
+            panic_message(|| check_missing("missing a.txt", "t", Some("boom"), true)),
+            None
+        );
+        assert_eq!(
+            panic_message(|| check_missing("missing a.txt", "t", Some(""), true)),
+            None
+        );
+    }
+}
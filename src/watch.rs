@@ -0,0 +1,253 @@
+//! Keeping deserialized secret values up to date as their files change.
+
+use crate::{deserialize_file, Error, Format, Result};
+use notify::{PollWatcher, RecommendedWatcher, RecursiveMode, Watcher};
+use serde::de::DeserializeOwned;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc, Arc, RwLock};
+use std::time::Duration;
+
+/// How long to wait after a change for further changes before reloading. Editors often write a
+/// file in several steps.
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// How often the file is checked when native file system events are not available.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+struct Shared<T> {
+    value: RwLock<Arc<T>>,
+    error: RwLock<Option<Arc<Error>>>,
+    version: AtomicU64,
+}
+
+/// A deserialized secret value that is reloaded whenever its file changes.
+///
+/// Created with `SourceWithFormat::watch()`:
+///
+/// ```text
+/// let token = secrets
+///     .read_from("token.json")
+///     .with_format(devsecrets::JsonFormat)
+///     .watch::<Token>()?;
+///
+/// // Later, always the latest version of the file:
+/// let current = token.current();
+/// ```
+///
+/// The file is watched with the operating system's file system events where available, and by
+/// polling otherwise. If the file is removed or can't be parsed after a change, the last good value
+/// is kept and the error is available from `last_error()`. The file stops being watched when the
+/// `WatchedValue` is dropped.
+pub struct WatchedValue<T> {
+    shared: Arc<Shared<T>>,
+    _watcher: Box<dyn Watcher + Send>,
+}
+
+impl<T> WatchedValue<T>
+where
+    T: DeserializeOwned + Send + Sync + 'static,
+{
+    pub(crate) fn new<F>(fullpath: PathBuf) -> Result<Self>
+    where
+        F: Format + Default + Send + 'static,
+    {
+        let value = deserialize_file(&F::default(), &fullpath)?;
+        let shared = Arc::new(Shared {
+            value: RwLock::new(Arc::new(value)),
+            error: RwLock::new(None),
+            version: AtomicU64::new(0),
+        });
+
+        let (sender, receiver) = mpsc::channel();
+        let watcher = start_watcher(&fullpath, sender)?;
+        let thread_shared = shared.clone();
+        std::thread::Builder::new()
+            .name("devsecrets-watch".to_string())
+            .spawn(move || reload_on_change::<T, F>(&fullpath, &thread_shared, &receiver))
+            .map_err(Error::IoError)?;
+
+        Ok(WatchedValue {
+            shared,
+            _watcher: watcher,
+        })
+    }
+}
+
+impl<T> WatchedValue<T> {
+    /// Returns the most recent value that was read successfully.
+    pub fn current(&self) -> Arc<T> {
+        self.shared
+            .value
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Returns the error from the last reload, or `None` if it succeeded.
+    pub fn last_error(&self) -> Option<Arc<Error>> {
+        self.shared
+            .error
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Returns the number of times the value has been reloaded successfully.
+    ///
+    /// This can be compared with an earlier result to find out whether the value changed.
+    pub fn version(&self) -> u64 {
+        self.shared.version.load(Ordering::SeqCst)
+    }
+}
+
+/// Watches the directory containing `fullpath`, sending a message whenever the file may have
+/// changed. The directory is watched, rather than the file, so that files replaced by renaming
+/// are noticed.
+fn start_watcher(fullpath: &Path, sender: mpsc::Sender<()>) -> Result<Box<dyn Watcher + Send>> {
+    let dir = fullpath
+        .parent()
+        .expect("Secret paths are within a directory");
+    // Some backends report canonical paths, such as FSEvents resolving `/var` to `/private/var`.
+    let dir = dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
+    let file_name = fullpath
+        .file_name()
+        .expect("Secret paths name a file")
+        .to_os_string();
+    let handler = move |event: notify::Result<notify::Event>| {
+        if let Ok(event) = event {
+            // Only the directory itself is watched, so comparing file names is enough, and
+            // avoids differences in how backends spell the directory.
+            if event
+                .paths
+                .iter()
+                .any(|path| path.file_name() == Some(file_name.as_os_str()))
+            {
+                // The receiver only goes away when the reload thread exits, which happens when
+                // this watcher is dropped.
+                let _ = sender.send(());
+            }
+        }
+    };
+
+    let native = RecommendedWatcher::new(handler.clone(), notify::Config::default()).and_then(
+        |mut watcher| {
+            watcher.watch(&dir, RecursiveMode::NonRecursive)?;
+            Ok(watcher)
+        },
+    );
+    if let Ok(watcher) = native {
+        return Ok(Box::new(watcher));
+    }
+
+    let mut watcher = PollWatcher::new(
+        handler,
+        notify::Config::default().with_poll_interval(POLL_INTERVAL),
+    )
+    .map_err(to_io_error)?;
+    watcher
+        .watch(&dir, RecursiveMode::NonRecursive)
+        .map_err(to_io_error)?;
+    Ok(Box::new(watcher))
+}
+
+fn to_io_error(error: notify::Error) -> Error {
+    match error.kind {
+        notify::ErrorKind::Io(e) => Error::IoError(e),
+        _ => Error::IoError(std::io::Error::other(error.to_string())),
+    }
+}
+
+fn reload_on_change<T, F>(fullpath: &Path, shared: &Shared<T>, receiver: &mpsc::Receiver<()>)
+where
+    T: DeserializeOwned,
+    F: Format + Default,
+{
+    while receiver.recv().is_ok() {
+        // Wait for the changes to settle before reading the file.
+        loop {
+            match receiver.recv_timeout(DEBOUNCE) {
+                Ok(()) => continue,
+                Err(mpsc::RecvTimeoutError::Timeout) => break,
+                Err(mpsc::RecvTimeoutError::Disconnected) => return,
+            }
+        }
+
+        let result = deserialize_file::<T, F>(&F::default(), fullpath);
+        let mut error = shared.error.write().unwrap_or_else(|e| e.into_inner());
+        match result {
+            Ok(value) => {
+                *shared.value.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(value);
+                shared.version.fetch_add(1, Ordering::SeqCst);
+                *error = None;
+            }
+            Err(e) => {
+                log::warn!("Keeping the last value of {:?}: {}", fullpath, e);
+                *error = Some(Arc::new(e));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::TempSecretsDir;
+    use crate::JsonFormat;
+    use serde::Deserialize;
+    use std::time::Instant;
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Token {
+        value: String,
+    }
+
+    /// Waits for `condition`, allowing for the debounce and for backends that poll.
+    fn wait_until(mut condition: impl FnMut() -> bool) {
+        let deadline = Instant::now() + super::POLL_INTERVAL * 10;
+        while !condition() {
+            assert!(Instant::now() < deadline, "Timed out waiting for a reload");
+            std::thread::sleep(super::DEBOUNCE / 4);
+        }
+    }
+
+    #[test]
+    fn rewriting_the_file_reloads_the_value() {
+        let dir = TempSecretsDir::new();
+        dir.write("token.json", r#"{ "value": "first" }"#);
+        let token = dir
+            .secrets()
+            .read_from("token.json")
+            .with_format(JsonFormat)
+            .watch::<Token>()
+            .unwrap();
+        assert_eq!(token.current().value, "first");
+        assert_eq!(token.version(), 0);
+
+        dir.write("token.json", r#"{ "value": "second" }"#);
+        wait_until(|| token.version() > 0);
+        assert_eq!(token.current().value, "second");
+        assert!(token.last_error().is_none());
+    }
+
+    #[test]
+    fn invalid_content_keeps_the_last_good_value() {
+        let dir = TempSecretsDir::new();
+        dir.write("token.json", r#"{ "value": "good" }"#);
+        let token = dir
+            .secrets()
+            .read_from("token.json")
+            .with_format(JsonFormat)
+            .watch::<Token>()
+            .unwrap();
+
+        dir.write("token.json", "not json");
+        wait_until(|| token.last_error().is_some());
+        assert_eq!(token.current().value, "good");
+        assert_eq!(token.version(), 0);
+
+        dir.write("token.json", r#"{ "value": "fixed" }"#);
+        wait_until(|| token.version() > 0);
+        assert_eq!(token.current().value, "fixed");
+        assert!(token.last_error().is_none());
+    }
+}